@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use reggle_path::{Path, PathCursor, PathMode, SegmentType, Vec2};
+
+fn circle_path(points: usize, typ: SegmentType) -> Path {
+    let positions: Vec<_> = (0..points)
+        .map(|i| Vec2::from_angle(std::f32::consts::TAU * i as f32 / points as f32) * 300.0)
+        .collect();
+    let mut path = Path::from_positions(&positions, typ, PathMode::Loop);
+    path.move_speed = 100.0;
+    path
+}
+
+fn tessellation(c: &mut Criterion) {
+    for (name, typ) in [
+        ("catmull_rom", SegmentType::CatmullRom(0.5)),
+        ("b_spline", SegmentType::BSpline),
+    ] {
+        let mut path = circle_path(16, typ);
+        c.bench_function(&format!("tessellate_{name}"), |b| {
+            b.iter(|| black_box(&mut path).tessellate_segments())
+        });
+    }
+}
+
+fn agents(c: &mut Criterion) {
+    let path = circle_path(16, SegmentType::CatmullRom(0.5));
+    let mut cursors: Vec<_> = (0..100).map(|i| PathCursor::new(i as f32 * 0.16)).collect();
+    let mut steps = Vec::new();
+    c.bench_function("move_100_agents", |b| {
+        b.iter(|| {
+            for cursor in cursors.iter_mut() {
+                steps.clear();
+                path.move_agent_along_path(cursor, black_box(1.0 / 60.0), &mut steps);
+                black_box(path.get_world_pos(cursor.t));
+            }
+        })
+    });
+}
+
+fn queries(c: &mut Criterion) {
+    let path = circle_path(16, SegmentType::CatmullRom(0.5));
+    c.bench_function("closest_point", |b| {
+        b.iter(|| path.closest_point(black_box(Vec2::new(120.0, -80.0))))
+    });
+    c.bench_function("pos_at_length", |b| {
+        b.iter(|| path.get_pos_at_length(black_box(path.length() * 0.7)))
+    });
+}
+
+criterion_group!(benches, tessellation, agents, queries);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+//! Spline and path math of reggle, kept free of ECS so level tooling can use it too.
+//!
+//! Enable the `bevy_reflect` feature to make the types reflectable, and `serde` to
+//! read and write them in level files.
+
+mod path;
+mod spline;
+
+pub use glam::Vec2;
+pub use path::{AgentStep, Path, PathCursor, PathEasingFunction, PathMode, Waypoint};
+pub use spline::{Segment, SegmentType, TessellationTolerance};
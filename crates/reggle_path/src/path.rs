@@ -0,0 +1,428 @@
+use glam::Vec2;
+
+use crate::spline::{Segment, SegmentType, TessellationTolerance};
+
+const MIN_SPEED_MULTIPLIER: f32 = 0.01;
+const BEZIER_SOLVE_ITER_NUM: usize = 8;
+
+/// What agents do when they reach an end of the path.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "bevy_reflect", derive(bevy_reflect::Reflect))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PathMode {
+    /// Path is closed and agents go around it forever.
+    Loop,
+    /// Agents turn around at both ends.
+    PingPong,
+    /// Agents stay at the end.
+    #[default]
+    OnceStop,
+    /// Agents are despawned at the end.
+    OnceDespawn,
+}
+
+/// How agents move within a segment. Everything except `None` keeps the segment's
+/// arc length in mind, so `Constant` moves with the same speed along the whole segment.
+#[derive(Default, Clone, Copy, Debug)]
+#[cfg_attr(feature = "bevy_reflect", derive(bevy_reflect::Reflect))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PathEasingFunction {
+    /// Follows spline parameter directly, speed depends on spacing of the points.
+    None,
+    #[default]
+    Constant,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    /// CSS-like timing curve from (0, 0) to (1, 1) with these two control points.
+    CubicBezier(Vec2, Vec2),
+}
+
+impl PathEasingFunction {
+    /// Maps linear progress along a segment to eased progress, both from 0.0 to 1.0.
+    pub fn ease(&self, t: f32) -> f32 {
+        match *self {
+            PathEasingFunction::None | PathEasingFunction::Constant => t,
+            PathEasingFunction::EaseIn => t * t,
+            PathEasingFunction::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            PathEasingFunction::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (2.0 - 2.0 * t).powi(2) / 2.0
+                }
+            }
+            PathEasingFunction::CubicBezier(p1, p2) => cubic_bezier_ease(p1, p2, t),
+        }
+    }
+}
+
+fn cubic_bezier(p1: f32, p2: f32, s: f32) -> f32 {
+    let inv = 1.0 - s;
+    3.0 * inv * inv * s * p1 + 3.0 * inv * s * s * p2 + s * s * s
+}
+
+fn cubic_bezier_derivative(p1: f32, p2: f32, s: f32) -> f32 {
+    let inv = 1.0 - s;
+    3.0 * inv * inv * p1 + 6.0 * inv * s * (p2 - p1) + 3.0 * s * s * (1.0 - p2)
+}
+
+/// Finds curve parameter where x equals `t` and returns y there.
+fn cubic_bezier_ease(p1: Vec2, p2: Vec2, t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    let (mut low, mut high) = (0.0f32, 1.0f32);
+    let mut s = t;
+    for _ in 0..BEZIER_SOLVE_ITER_NUM {
+        let x = cubic_bezier(p1.x, p2.x, s) - t;
+        if x.abs() < 1e-5 {
+            break;
+        }
+        if x > 0.0 {
+            high = s;
+        } else {
+            low = s;
+        }
+        // Newton step, falling back to bisection when it leaves the bracket
+        let dx = cubic_bezier_derivative(p1.x, p2.x, s);
+        let next = s - x / dx;
+        s = if dx.abs() > 1e-6 && next > low && next < high {
+            next
+        } else {
+            (low + high) / 2.0
+        };
+    }
+    cubic_bezier(p1.y, p2.y, s)
+}
+
+/// Point of a [`Path`] together with the segment that starts at it.
+#[derive(Clone, Debug)]
+pub struct Waypoint {
+    pub pos: Vec2,
+    pub segment: Segment,
+    /// Multiplies path move speed on the segment starting at this point.
+    pub speed_multiplier: f32,
+    pub easing_function: PathEasingFunction,
+    /// Seconds agents wait after reaching this point.
+    pub pause: f32,
+}
+
+impl Waypoint {
+    pub fn new(pos: Vec2, segment_type: SegmentType) -> Self {
+        Self {
+            pos,
+            segment: Segment::new(segment_type),
+            speed_multiplier: 1.0,
+            easing_function: PathEasingFunction::default(),
+            pause: 0.0,
+        }
+    }
+}
+
+/// Position of an agent on a [`Path`].
+#[derive(Default, Clone, Copy, Debug)]
+#[cfg_attr(feature = "bevy_reflect", derive(bevy_reflect::Reflect))]
+pub struct PathCursor {
+    /// Integer part is the index of the segment, fraction is the part of its length passed.
+    pub t: f32,
+    /// Agent moves from the last point towards the first one.
+    pub reversed: bool,
+    /// Seconds left to wait at the point agent has reached.
+    pub pause_left: f32,
+}
+
+impl PathCursor {
+    pub fn new(t: f32) -> Self {
+        Self {
+            t,
+            ..Default::default()
+        }
+    }
+}
+
+/// Something that happened to an agent while it was moved along the path.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AgentStep {
+    /// Index of the point agent reached.
+    ReachedPoint(usize),
+    CompletedLoop,
+}
+
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "bevy_reflect", derive(bevy_reflect::Reflect))]
+pub struct Path {
+    #[cfg_attr(feature = "bevy_reflect", reflect(ignore))]
+    pub points: Vec<Waypoint>,
+    pub move_speed: f32,
+    pub mode: PathMode,
+    pub tolerance: TessellationTolerance,
+}
+
+impl Path {
+    pub fn new(move_speed: f32, mode: PathMode) -> Self {
+        Self {
+            move_speed,
+            mode,
+            ..Default::default()
+        }
+    }
+    /// Tessellated path going through `positions`.
+    pub fn from_positions(positions: &[Vec2], segment_type: SegmentType, mode: PathMode) -> Self {
+        let mut path = Self {
+            points: positions
+                .iter()
+                .map(|&pos| Waypoint::new(pos, segment_type))
+                .collect(),
+            mode,
+            ..Default::default()
+        };
+        path.tessellate_segments();
+        path
+    }
+    /// Whether the last point connects back to the first one.
+    pub fn is_looped(&self) -> bool {
+        self.mode == PathMode::Loop
+    }
+    pub fn is_valid(&self) -> bool {
+        !self.points.is_empty()
+    }
+    /// Position of an agent at `t`, with the easing of the segment applied.
+    pub fn get_world_pos(&self, t: f32) -> Vec2 {
+        self.get_world_pos_and_vel(t).0
+    }
+
+    /// Position and tangent of an agent at `t`. Unlike `get_pos_and_vel`, the easing of the
+    /// segment is applied first, so the tangent is taken where the agent actually is.
+    pub fn get_world_pos_and_vel(&self, t: f32) -> (Vec2, Vec2) {
+        if !self.is_valid() {
+            return (Vec2::ZERO, Vec2::ZERO);
+        }
+        let point_idx = (t.floor() as usize).min(self.segment_count().saturating_sub(1));
+        let point = &self.points[point_idx];
+        let Some(neigbors) = self.get_neigbors_positions(point_idx) else {
+            return (point.pos, Vec2::ZERO);
+        };
+
+        if let PathEasingFunction::None = point.easing_function {
+            point
+                .segment
+                .get_pos_and_vel(t - point_idx as f32, &neigbors)
+        } else {
+            // Eased progress is a fraction of the segment length, not a spline parameter
+            let eased_t = point.easing_function.ease(t - point_idx as f32);
+            let param = point.segment.param_at(eased_t).unwrap_or(eased_t);
+            let (pos, vel) = point.segment.get_pos_and_vel(param, &neigbors);
+            (point.segment.get_pos_cached(eased_t).unwrap_or(pos), vel)
+        }
+    }
+
+    pub fn segment_count(&self) -> usize {
+        if self.is_looped() {
+            self.points.len()
+        } else {
+            self.points.len().saturating_sub(1)
+        }
+    }
+
+    /// Total length of all segments.
+    pub fn length(&self) -> f32 {
+        self.points[..self.segment_count()]
+            .iter()
+            .map(|p| p.segment.len())
+            .sum()
+    }
+
+    /// Length along the path from the first point to `t`.
+    pub fn length_at(&self, t: f32) -> f32 {
+        let segments = self.segment_count();
+        if segments == 0 {
+            return 0.0;
+        }
+        let t = t.clamp(0.0, segments as f32);
+        let point_idx = (t.floor() as usize).min(segments - 1);
+        let before: f32 = self.points[..point_idx]
+            .iter()
+            .map(|p| p.segment.len())
+            .sum();
+        before + (t - point_idx as f32) * self.points[point_idx].segment.len()
+    }
+
+    /// Point of the path closest to `pos`, as `(t, distance)`.
+    pub fn closest_point(&self, pos: Vec2) -> Option<(f32, f32)> {
+        self.points[..self.segment_count()]
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| {
+                let (t, distance) = p.segment.closest_point(pos)?;
+                Some((t + i as f32, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Path `t` at `length` measured along the path from the first point.
+    /// Wraps around looped paths and is clamped to the ends of the others.
+    pub fn t_at_length(&self, length: f32) -> f32 {
+        let total = self.length();
+        if total < f32::EPSILON {
+            return 0.0;
+        }
+        let mut length = if self.is_looped() {
+            length.rem_euclid(total)
+        } else {
+            length.clamp(0.0, total)
+        };
+        for (i, point) in self.points[..self.segment_count()].iter().enumerate() {
+            let seg_len = point.segment.len();
+            if length <= seg_len && seg_len > 0.0 {
+                return i as f32 + length / seg_len;
+            }
+            length -= seg_len;
+        }
+        self.segment_count() as f32
+    }
+
+    /// Position at `length` measured along the path from the first point.
+    pub fn get_pos_at_length(&self, length: f32) -> Vec2 {
+        if !self.is_valid() {
+            return Vec2::ZERO;
+        }
+        let t = self.t_at_length(length);
+        let point_idx = (t.floor() as usize).min(self.segment_count().saturating_sub(1));
+        let point = &self.points[point_idx];
+        point
+            .segment
+            .get_pos_cached(t - point_idx as f32)
+            .unwrap_or(point.pos)
+    }
+
+    /// Position and tangent at `t`, where the integer part of `t` is the segment index.
+    pub fn get_pos_and_vel(&self, t: f32) -> (Vec2, Vec2) {
+        if !self.is_valid() {
+            return (Vec2::ZERO, Vec2::ZERO);
+        }
+        let point_idx = (t.floor() as usize).min(self.segment_count().saturating_sub(1));
+        let point = &self.points[point_idx];
+        let Some(neigbors) = self.get_neigbors_positions(point_idx) else {
+            return (point.pos, Vec2::ZERO);
+        };
+        point
+            .segment
+            .get_pos_and_vel(t - point_idx as f32, &neigbors)
+    }
+
+    pub fn tessellate_segments(&mut self) {
+        let tolerance = self.tolerance;
+        for i in 0..self.points.len() {
+            let neighbors = self.get_neigbors_positions(i);
+            self.points[i]
+                .segment
+                .tessellate(neighbors.as_ref(), &tolerance);
+        }
+    }
+
+    /// Positions of the points around the segment starting at `point_idx`, or `None` if
+    /// there is no such segment.
+    pub fn get_neigbors_positions(&self, point_idx: usize) -> Option<[Vec2; 4]> {
+        if self.points.len() < 2 || point_idx >= self.segment_count() {
+            return None;
+        }
+        let p1 = self.points[point_idx].pos;
+        let p2 = self.points[(point_idx + 1) % self.points.len()].pos;
+        let (p0, p3) = if self.is_looped() {
+            (
+                self.points[point_idx
+                    .checked_add_signed(-1)
+                    .unwrap_or(self.points.len() - 1)]
+                .pos,
+                self.points[(point_idx + 2) % self.points.len()].pos,
+            )
+        } else {
+            let dir = (p2 - p1).normalize_or_zero();
+            let p0 = if point_idx > 0 {
+                self.points[point_idx - 1].pos
+            } else {
+                p1 - dir
+            };
+            let p3 = if point_idx < self.points.len() - 2 {
+                self.points[point_idx + 2].pos
+            } else {
+                p2 + dir
+            };
+            (p0, p3)
+        };
+        Some([p0, p1, p2, p3])
+    }
+
+    /// Moves agent along the path according to the path mode, recording what happened on the way.
+    /// Returns `false` once the agent finished a [`PathMode::OnceDespawn`] path.
+    pub fn move_agent_along_path(
+        &self,
+        agent: &mut PathCursor,
+        time_delta: f32,
+        steps: &mut Vec<AgentStep>,
+    ) -> bool {
+        let segments = self.segment_count();
+        if segments == 0 {
+            return true;
+        }
+        let end = segments as f32;
+
+        let paused = agent.pause_left.min(time_delta);
+        agent.pause_left -= paused;
+        let mut agent_distance = self.move_speed * (time_delta - paused);
+        // Every point can be passed at most once per direction, guards against zero-length paths
+        for _ in 0..=segments * 2 {
+            let at_end = if agent.reversed {
+                agent.t <= 0.0
+            } else {
+                agent.t >= end
+            };
+            if at_end {
+                match self.mode {
+                    PathMode::Loop => {
+                        agent.t = if agent.reversed { end } else { 0.0 };
+                        steps.push(AgentStep::CompletedLoop);
+                    }
+                    PathMode::PingPong => {
+                        if agent.reversed {
+                            steps.push(AgentStep::CompletedLoop);
+                        }
+                        agent.reversed = !agent.reversed;
+                    }
+                    PathMode::OnceStop => return true,
+                    PathMode::OnceDespawn => return false,
+                }
+            }
+            if agent.pause_left > 0.0 || agent_distance <= 0.0 {
+                break;
+            }
+
+            let (point_idx, next_t) = if agent.reversed {
+                let idx = (agent.t.ceil() as usize)
+                    .saturating_sub(1)
+                    .min(segments - 1);
+                (idx, idx as f32)
+            } else {
+                let idx = (agent.t.floor() as usize).min(segments - 1);
+                (idx, idx as f32 + 1.0)
+            };
+            let point = &self.points[point_idx];
+            // Slower segments take longer to cross, as if they were longer
+            let seg_len = point.segment.len() / point.speed_multiplier.max(MIN_SPEED_MULTIPLIER);
+            let seg_distance = (next_t - agent.t).abs() * seg_len;
+            if seg_distance.is_nan() {
+                break;
+            }
+            if seg_distance > agent_distance {
+                let step = agent_distance / seg_len;
+                agent.t += if agent.reversed { -step } else { step };
+                break;
+            }
+            agent_distance -= seg_distance;
+            agent.t = next_t;
+            let reached_idx = next_t as usize % self.points.len();
+            agent.pause_left = self.points[reached_idx].pause;
+            steps.push(AgentStep::ReachedPoint(reached_idx));
+        }
+        true
+    }
+}
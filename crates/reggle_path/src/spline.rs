@@ -0,0 +1,383 @@
+use glam::Vec2;
+
+const SEGMENTS_INITIAL_SPLITS: usize = 4;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "bevy_reflect", derive(bevy_reflect::Reflect))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SegmentType {
+    None,
+    Linear,
+    CatmullRom(f32),
+    /// Cubic Bezier with control handles relative to the segment start and end points.
+    CubicBezier {
+        out_handle: Vec2,
+        in_handle: Vec2,
+    },
+    /// Cubic Hermite with explicit tangents at the segment start and end points.
+    Hermite {
+        start_tangent: Vec2,
+        end_tangent: Vec2,
+    },
+    /// Uniform cubic B-spline. Smooth, but doesn't pass through the points.
+    BSpline,
+}
+
+impl Default for SegmentType {
+    fn default() -> Self {
+        SegmentType::CatmullRom(0.5)
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "bevy_reflect", derive(bevy_reflect::Reflect))]
+pub struct Segment {
+    pub typ: SegmentType,
+    len: f32,
+    #[cfg_attr(feature = "bevy_reflect", reflect(ignore))]
+    points: Vec<Vec2>,
+    #[cfg_attr(feature = "bevy_reflect", reflect(ignore))]
+    distnaces: Vec<f32>,
+    /// Spline parameter of every tessellated point.
+    #[cfg_attr(feature = "bevy_reflect", reflect(ignore))]
+    params: Vec<f32>,
+}
+
+impl Segment {
+    pub fn new(typ: SegmentType) -> Self {
+        Self {
+            typ,
+            ..Default::default()
+        }
+    }
+    pub fn points(&self) -> &[Vec2] {
+        &self.points
+    }
+    pub fn len(&self) -> f32 {
+        self.len
+    }
+    pub fn get_pos(&self, mut t: f32, neighbors: &[Vec2; 4]) -> Vec2 {
+        t = t.clamp(0.0, 1.0);
+        match self.typ {
+            SegmentType::None => neighbors[1],
+            SegmentType::Linear => {
+                let p2 = neighbors[2];
+                neighbors[1] + (p2 - neighbors[1]) * t
+            }
+            SegmentType::CatmullRom(alpha) => catmull_rom_spline(
+                neighbors[0],
+                neighbors[1],
+                neighbors[2],
+                neighbors[3],
+                alpha,
+                t,
+            ),
+            _ => self.get_pos_and_vel(t, neighbors).0,
+        }
+    }
+
+    pub fn get_pos_cached(&self, t: f32) -> Option<Vec2> {
+        let Some((idx, frac)) = self.locate(t) else {return self.points.first().copied()};
+        Some(self.points[idx].lerp(self.points[idx + 1], frac))
+    }
+
+    /// Spline parameter at `t` fraction of the segment length, so tangents can be taken
+    /// at the same point of the curve as `get_pos_cached` positions.
+    pub fn param_at(&self, t: f32) -> Option<f32> {
+        let (idx, frac) = self.locate(t)?;
+        let (p0, p1) = (self.params[idx], self.params[idx + 1]);
+        Some(p0 + (p1 - p0) * frac)
+    }
+
+    /// Piece of the tessellated segment at `t` fraction of its length, as the index of the
+    /// point the piece starts at and the fraction of the piece passed.
+    fn locate(&self, mut t: f32) -> Option<(usize, f32)> {
+        t = t.clamp(0.0, 1.0);
+        let points = self.points();
+        let distances = &self.distnaces;
+        if points.len() < 2 {
+            return None;
+        }
+        if points.len() == 2 {
+            return Some((0, t));
+        }
+
+        let target_distnace = self.len() * t;
+        let mut start = 0;
+        let mut end = distances.len();
+        let mut idx = (start + end) / 2;
+        while start < idx {
+            if target_distnace <= distances[idx] {
+                end = idx;
+            } else {
+                start = idx;
+            }
+            idx = (start + end) / 2;
+        }
+        let idx = idx.min(points.len() - 2);
+
+        let line_length = (points[idx + 1] - points[idx]).length();
+        let frac = if line_length < f32::EPSILON {
+            0.5
+        } else {
+            (target_distnace - distances[idx]) / line_length
+        };
+        Some((idx, frac))
+    }
+
+    /// Point of the tessellated segment closest to `pos`, as `(t, distance)`.
+    /// `t` is a fraction of the segment length, same as in `get_pos_cached`.
+    pub fn closest_point(&self, pos: Vec2) -> Option<(f32, f32)> {
+        let points = self.points();
+        let first = *points.first()?;
+        if points.len() == 1 || self.len < f32::EPSILON {
+            return Some((0.0, first.distance(pos)));
+        }
+        let mut closest = (0.0, f32::INFINITY);
+        let mut traveled = 0.0;
+        for (p1, p2) in points.iter().zip(&points[1..]) {
+            let line_vec = *p2 - *p1;
+            let line_length = line_vec.length();
+            let frac = if line_length < f32::EPSILON {
+                0.0
+            } else {
+                ((pos - *p1).dot(line_vec) / (line_length * line_length)).clamp(0.0, 1.0)
+            };
+            let distance = (*p1 + line_vec * frac).distance(pos);
+            if distance < closest.1 {
+                closest = ((traveled + line_length * frac) / self.len, distance);
+            }
+            traveled += line_length;
+        }
+        Some(closest)
+    }
+
+    pub fn get_pos_and_vel(&self, mut t: f32, neighbors: &[Vec2; 4]) -> (Vec2, Vec2) {
+        t = t.clamp(0.0, 1.0);
+        match self.typ {
+            SegmentType::None => (neighbors[1], Vec2::ZERO),
+            SegmentType::Linear => {
+                let p1 = neighbors[1];
+                let p2 = neighbors[2];
+                (p1 + (p2 - p1) * t, (p2 - p1).normalize_or_zero())
+            }
+            SegmentType::CatmullRom(alpha) => catmull_rom_spline_with_derivative(
+                neighbors[0],
+                neighbors[1],
+                neighbors[2],
+                neighbors[3],
+                alpha,
+                t,
+            ),
+            SegmentType::CubicBezier {
+                out_handle,
+                in_handle,
+            } => {
+                let [_, p1, p2, _] = *neighbors;
+                cubic_bezier_with_derivative(p1, p1 + out_handle, p2 + in_handle, p2, t)
+            }
+            SegmentType::Hermite {
+                start_tangent,
+                end_tangent,
+            } => hermite_with_derivative(neighbors[1], start_tangent, neighbors[2], end_tangent, t),
+            SegmentType::BSpline => b_spline_with_derivative(neighbors, t),
+        }
+    }
+
+    /// Approximates segment with a polyline that is never further than
+    /// `tolerance.max_chord_error` from the curve.
+    pub fn tessellate(&mut self, neighbors: Option<&[Vec2; 4]>, tolerance: &TessellationTolerance) {
+        self.points.clear();
+        self.distnaces.clear();
+        self.params.clear();
+        self.len = 0.0;
+        let Some(neighbors) = neighbors else {return};
+        if let SegmentType::Linear = self.typ {
+            self.points.push(neighbors[1]);
+            self.points.push(neighbors[2]);
+            self.params.extend([0.0, 1.0]);
+            self.len = (neighbors[2] - neighbors[1]).length();
+            self.distnaces.push(self.len);
+            return;
+        }
+        let pos = |t: f32| self.get_pos_and_vel(t, neighbors).0;
+        let mut points = vec![(0.0, pos(0.0))];
+        // Symmetric curves can have their midpoint right on the chord, so start from a few pieces
+        for i in 0..SEGMENTS_INITIAL_SPLITS {
+            let t0 = i as f32 / SEGMENTS_INITIAL_SPLITS as f32;
+            let t1 = (i + 1) as f32 / SEGMENTS_INITIAL_SPLITS as f32;
+            subdivide(
+                &pos,
+                (t0, pos(t0)),
+                (t1, pos(t1)),
+                tolerance,
+                0,
+                &mut points,
+            );
+        }
+
+        (self.params, self.points) = points.into_iter().unzip();
+        let mut x_last = self.points[0];
+        for &x in self.points.iter().skip(1) {
+            self.distnaces.push(self.len);
+            self.len += (x - x_last).length();
+            x_last = x;
+        }
+    }
+}
+
+/// Limits how far tessellated polylines may stray from the curve.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "bevy_reflect", derive(bevy_reflect::Reflect))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TessellationTolerance {
+    /// Largest allowed distance between the curve and a polyline chord, in pixels.
+    pub max_chord_error: f32,
+    /// Limits how many times a piece of the curve can be halved.
+    pub max_depth: u32,
+}
+
+impl Default for TessellationTolerance {
+    fn default() -> Self {
+        Self {
+            max_chord_error: 0.25,
+            max_depth: 10,
+        }
+    }
+}
+
+/// Halves the curve between `start` and `end` until chords fit the tolerance,
+/// pushing every point after `start` into `out` together with its parameter.
+fn subdivide(
+    pos: &impl Fn(f32) -> Vec2,
+    start: (f32, Vec2),
+    end: (f32, Vec2),
+    tolerance: &TessellationTolerance,
+    depth: u32,
+    out: &mut Vec<(f32, Vec2)>,
+) {
+    let (t0, p0) = start;
+    let (t1, p1) = end;
+    let tm = (t0 + t1) / 2.0;
+    let pm = pos(tm);
+    let error = [pos(t0 + (t1 - t0) * 0.25), pm, pos(t0 + (t1 - t0) * 0.75)]
+        .into_iter()
+        .map(|p| distance_to_chord(p, p0, p1))
+        .fold(0.0, f32::max);
+    if error > tolerance.max_chord_error && depth < tolerance.max_depth {
+        subdivide(pos, start, (tm, pm), tolerance, depth + 1, out);
+        subdivide(pos, (tm, pm), end, tolerance, depth + 1, out);
+    } else {
+        out.push(end);
+    }
+}
+
+fn distance_to_chord(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let chord = b - a;
+    let length_sq = chord.length_squared();
+    if length_sq < f32::EPSILON {
+        return p.distance(a);
+    }
+    let frac = ((p - a).dot(chord) / length_sq).clamp(0.0, 1.0);
+    p.distance(a + chord * frac)
+}
+
+/// Implementation of centripetal Catmull–Rom spline.
+/// See: https://en.wikipedia.org/wiki/Centripetal_Catmull%E2%80%93Rom_spline#Definition
+fn catmull_rom_spline(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, mut alpha: f32, mut t: f32) -> Vec2 {
+    t = t.clamp(0.0, 1.0);
+    alpha = alpha.clamp(0.0, 1.0);
+
+    let t0 = 0.0;
+    let t1 = (p1 - p0).length_squared().powf(alpha * 0.5) + t0;
+    let t2 = (p2 - p1).length_squared().powf(alpha * 0.5) + t1;
+    let t3 = (p3 - p2).length_squared().powf(alpha * 0.5) + t2;
+
+    t = t1 + t * (t2 - t1);
+
+    let a1 = (t1 - t) / (t1 - t0) * p0 + (t - t0) / (t1 - t0) * p1;
+    let a2 = (t2 - t) / (t2 - t1) * p1 + (t - t1) / (t2 - t1) * p2;
+    let a3 = (t3 - t) / (t3 - t2) * p2 + (t - t2) / (t3 - t2) * p3;
+
+    let b1 = (t2 - t) / (t2 - t0) * a1 + (t - t0) / (t2 - t0) * a2;
+    let b2 = (t3 - t) / (t3 - t1) * a2 + (t - t1) / (t3 - t1) * a3;
+
+    (t2 - t) / (t2 - t1) * b1 + (t - t1) / (t2 - t1) * b2
+}
+
+fn catmull_rom_spline_with_derivative(
+    p0: Vec2,
+    p1: Vec2,
+    p2: Vec2,
+    p3: Vec2,
+    mut alpha: f32,
+    mut t: f32,
+) -> (Vec2, Vec2) {
+    t = t.clamp(0.0, 1.0);
+    alpha = alpha.clamp(0.0, 1.0);
+
+    let t0 = 0.0;
+    let t1 = (p1 - p0).length_squared().powf(alpha * 0.5) + t0;
+    let t2 = (p2 - p1).length_squared().powf(alpha * 0.5) + t1;
+    let t3 = (p3 - p2).length_squared().powf(alpha * 0.5) + t2;
+
+    t = t1 + t * (t2 - t1);
+
+    let a1 = (t1 - t) / (t1 - t0) * p0 + (t - t0) / (t1 - t0) * p1;
+    let a2 = (t2 - t) / (t2 - t1) * p1 + (t - t1) / (t2 - t1) * p2;
+    let a3 = (t3 - t) / (t3 - t2) * p2 + (t - t2) / (t3 - t2) * p3;
+
+    let b1 = (t2 - t) / (t2 - t0) * a1 + (t - t0) / (t2 - t0) * a2;
+    let b2 = (t3 - t) / (t3 - t1) * a2 + (t - t1) / (t3 - t1) * a3;
+
+    let c = (t2 - t) / (t2 - t1) * b1 + (t - t1) / (t2 - t1) * b2;
+
+    let da1 = 1.0 / (t1 - t0) * (p1 - p0);
+    let da2 = 1.0 / (t2 - t1) * (p2 - p1);
+    let da3 = 1.0 / (t3 - t2) * (p3 - p2);
+
+    let db1 = 1.0 / (t2 - t0) * (a2 - a1) + (t2 - t) / (t2 - t0) * da1 + (t - t0) / (t2 - t0) * da2;
+    let db2 = 1.0 / (t3 - t1) * (a3 - a2) + (t3 - t) / (t3 - t1) * da2 + (t - t1) / (t3 - t1) * da3;
+
+    let dc = 1.0 / (t2 - t1) * (b2 - b1) + (t2 - t) / (t2 - t1) * db1 + (t - t1) / (t2 - t1) * db2;
+
+    (c, dc)
+}
+
+fn cubic_bezier_with_derivative(p0: Vec2, c1: Vec2, c2: Vec2, p3: Vec2, t: f32) -> (Vec2, Vec2) {
+    let inv = 1.0 - t;
+    let pos =
+        inv * inv * inv * p0 + 3.0 * inv * inv * t * c1 + 3.0 * inv * t * t * c2 + t * t * t * p3;
+    let vel = 3.0 * inv * inv * (c1 - p0) + 6.0 * inv * t * (c2 - c1) + 3.0 * t * t * (p3 - c2);
+    (pos, vel)
+}
+
+fn hermite_with_derivative(p1: Vec2, m1: Vec2, p2: Vec2, m2: Vec2, t: f32) -> (Vec2, Vec2) {
+    let (t2, t3) = (t * t, t * t * t);
+    let pos = (2.0 * t3 - 3.0 * t2 + 1.0) * p1
+        + (t3 - 2.0 * t2 + t) * m1
+        + (-2.0 * t3 + 3.0 * t2) * p2
+        + (t3 - t2) * m2;
+    let vel = (6.0 * t2 - 6.0 * t) * p1
+        + (3.0 * t2 - 4.0 * t + 1.0) * m1
+        + (-6.0 * t2 + 6.0 * t) * p2
+        + (3.0 * t2 - 2.0 * t) * m2;
+    (pos, vel)
+}
+
+fn b_spline_with_derivative(p: &[Vec2; 4], t: f32) -> (Vec2, Vec2) {
+    let (t2, t3) = (t * t, t * t * t);
+    let inv = 1.0 - t;
+    let pos = (inv * inv * inv * p[0]
+        + (3.0 * t3 - 6.0 * t2 + 4.0) * p[1]
+        + (-3.0 * t3 + 3.0 * t2 + 3.0 * t + 1.0) * p[2]
+        + t3 * p[3])
+        / 6.0;
+    let vel = (-3.0 * inv * inv * p[0]
+        + (9.0 * t2 - 12.0 * t) * p[1]
+        + (-9.0 * t2 + 6.0 * t + 3.0) * p[2]
+        + 3.0 * t2 * p[3])
+        / 6.0;
+    (pos, vel)
+}
@@ -0,0 +1,148 @@
+use proptest::prelude::*;
+use reggle_path::{Path, PathCursor, PathMode, SegmentType, Vec2};
+
+/// Closed polygon around the origin, points are kept apart so segments are never degenerate.
+fn looped_positions() -> impl Strategy<Value = Vec<Vec2>> {
+    prop::collection::vec((100.0f32..400.0, 0.2f32..0.8), 3..8).prop_map(|points| {
+        let step = std::f32::consts::TAU / points.len() as f32;
+        points
+            .iter()
+            .enumerate()
+            .map(|(i, &(radius, jitter))| Vec2::from_angle((i as f32 + jitter) * step) * radius)
+            .collect()
+    })
+}
+
+fn segment_type() -> impl Strategy<Value = SegmentType> {
+    prop_oneof![
+        Just(SegmentType::Linear),
+        (0.0f32..=1.0).prop_map(SegmentType::CatmullRom),
+        Just(SegmentType::BSpline),
+    ]
+}
+
+fn looped_path() -> impl Strategy<Value = Path> {
+    (looped_positions(), segment_type())
+        .prop_map(|(positions, typ)| Path::from_positions(&positions, typ, PathMode::Loop))
+}
+
+fn path_mode() -> impl Strategy<Value = PathMode> {
+    prop_oneof![
+        Just(PathMode::Loop),
+        Just(PathMode::PingPong),
+        Just(PathMode::OnceStop),
+        Just(PathMode::OnceDespawn),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn constant_speed_covers_equal_length_per_step(
+        mut path in looped_path(),
+        speed in 10.0f32..500.0,
+        dt in 0.001f32..0.05,
+    ) {
+        path.move_speed = speed;
+        let total = path.length();
+        let mut cursor = PathCursor::new(0.0);
+        let mut steps = Vec::new();
+        let mut length = path.length_at(cursor.t);
+        let expected = speed * dt;
+        let mut travelled = 0.0;
+        for _ in 0..200 {
+            prop_assert!(path.move_agent_along_path(&mut cursor, dt, &mut steps));
+            // Distance along the curve, wrapping around the seam
+            let next = path.length_at(cursor.t);
+            let covered = (next - length).rem_euclid(total);
+            prop_assert!(
+                (covered - expected).abs() <= expected * 1e-3 + 1e-3,
+                "covered {covered} instead of {expected} at t {}",
+                cursor.t
+            );
+            // Agents are drawn where the length along the curve says they are
+            let pos = path.get_world_pos(cursor.t);
+            prop_assert!(pos.distance(path.get_pos_at_length(next)) < 1e-2);
+            travelled += covered;
+            length = next;
+        }
+        let travelled_expected = 200.0 * expected;
+        prop_assert!((travelled - travelled_expected).abs() <= travelled_expected * 1e-3);
+    }
+
+    #[test]
+    fn degenerate_paths_do_not_panic(
+        positions in prop::collection::vec((-400.0f32..400.0, -400.0f32..400.0), 0..2),
+        mode in path_mode(),
+        typ in segment_type(),
+        t in -2.0f32..3.0,
+    ) {
+        let positions: Vec<Vec2> = positions.into_iter().map(Vec2::from).collect();
+        let mut path = Path::from_positions(&positions, typ, mode);
+        path.tessellate_segments();
+        let expected = positions.first().copied().unwrap_or(Vec2::ZERO);
+        prop_assert_eq!(path.get_world_pos(t), expected);
+        prop_assert_eq!(path.get_pos_and_vel(t).0, expected);
+        prop_assert_eq!(path.get_world_pos_and_vel(t).0, expected);
+        prop_assert_eq!(path.get_pos_at_length(t), expected);
+        prop_assert_eq!(path.length(), 0.0);
+        prop_assert_eq!(path.length_at(t), 0.0);
+        prop_assert_eq!(path.t_at_length(t), 0.0);
+        prop_assert!(path.get_neigbors_positions(1).is_none());
+
+        let mut cursor = PathCursor::new(t);
+        let mut steps = Vec::new();
+        path.move_agent_along_path(&mut cursor, 0.1, &mut steps);
+        prop_assert!(cursor.t.is_finite());
+    }
+
+    #[test]
+    fn looped_path_is_continuous_at_seam(path in looped_path()) {
+        let end = path.segment_count() as f32;
+        let start = path.get_world_pos(0.0);
+        prop_assert!(start.distance(path.get_world_pos(end - 1e-4)) < 0.5);
+        prop_assert!(start.distance(path.get_pos_at_length(path.length())) < 0.5);
+
+        // Linear paths have a corner at every point, the seam included
+        if !matches!(path.points[0].segment.typ, SegmentType::Linear) {
+            let (_, start_vel) = path.get_pos_and_vel(0.0);
+            let (_, end_vel) = path.get_pos_and_vel(end - 1e-4);
+            prop_assert!(start_vel.normalize().dot(end_vel.normalize()) > 0.99);
+        }
+    }
+
+    #[test]
+    fn tangent_points_where_agent_moves(
+        path in looped_path(),
+        segment in 0usize..8,
+        frac in 0.05f32..0.95,
+    ) {
+        let t = (segment % path.segment_count()) as f32 + frac;
+        let (pos, vel) = path.get_world_pos_and_vel(t);
+        let moved = path.get_world_pos(t + 1e-2) - pos;
+        // Agents move along polyline chords, which stray a few degrees from the curve
+        prop_assert!(vel.normalize().dot(moved.normalize()) > 0.98);
+    }
+
+    #[test]
+    fn length_round_trips_through_t(path in looped_path(), ratio in 0.0f32..1.0) {
+        let length = path.length() * ratio;
+        let t = path.t_at_length(length);
+        prop_assert!((path.length_at(t) - length).abs() < 1e-2);
+    }
+
+    #[test]
+    fn tessellation_stays_within_tolerance(path in looped_path(), ratio in 0.0f32..1.0) {
+        let t = path.segment_count() as f32 * ratio;
+        let (on_curve, _) = path.get_pos_and_vel(t);
+        let (_, distance) = path.closest_point(on_curve).unwrap();
+        prop_assert!(distance <= path.tolerance.max_chord_error + 1e-2);
+    }
+
+    #[test]
+    fn closest_point_of_tessellated_point_is_itself(path in looped_path(), length in 0.0f32..1.0) {
+        let pos = path.get_pos_at_length(path.length() * length);
+        let (t, distance) = path.closest_point(pos).unwrap();
+        prop_assert!(distance < 1e-2);
+        prop_assert!(path.get_pos_at_length(path.length_at(t)).distance(pos) < 1e-2);
+    }
+}
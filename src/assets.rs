@@ -1,34 +1,259 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
 
-use bevy::{asset::LoadState, prelude::*};
+use bevy::asset::{Asset, AssetLoader, LoadContext, LoadState, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::{BoxedFuture, HashMap};
 use bevy_kira_audio::AudioSource;
+use serde::Deserialize;
 
+use crate::campaign::{LevelPack, LevelPacks};
 use crate::common::GameState;
+use crate::level::Level;
+use crate::peg_type::PegTypeDefs;
+use crate::sounds::{CollisionSound, SoundBank};
+use crate::theme::{PegColors, Theme, Themes};
+
+const MANIFEST_PATH: &str = "assets.manifest.ron";
+/// Level packs are found in `packs/<id>/*.pack.ron`.
+const PACKS_DIR: &str = "packs";
 
 pub struct AssetsPlugin;
 
 impl Plugin for AssetsPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<GameAssets>()
-            .add_systems(OnEnter(GameState::LoadingAssets), load_assets)
+            .init_resource::<LoadingProgress>()
+            .add_asset::<AssetManifest>()
+            .init_asset_loader::<AssetManifestLoader>()
+            .add_systems(OnEnter(GameState::LoadingAssets), load_manifest)
             .add_systems(
                 PreUpdate,
-                check_load_status.run_if(in_state(GameState::LoadingAssets)),
+                (load_assets, check_load_status)
+                    .chain()
+                    .run_if(in_state(GameState::LoadingAssets)),
             );
     }
 }
 
+/// Asset paths of one group, split by asset kind and keyed by name.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AssetGroup {
+    pub themes: HashMap<String, String>,
+    pub peg_types: HashMap<String, String>,
+    pub levels: HashMap<String, String>,
+    /// Packs that are loaded even if the packs directory can't be listed, as in the browser.
+    pub packs: HashMap<String, String>,
+}
+
+/// Describes every asset the game loads before starting.
+#[derive(Deserialize, TypeUuid, TypePath, Default)]
+#[uuid = "c3a6f0d2-8e4b-4f1a-b7d9-1e2f3a4b5c6d"]
+pub struct AssetManifest {
+    pub groups: HashMap<String, AssetGroup>,
+}
+
+#[derive(Default)]
+pub struct AssetManifestLoader;
+
+impl AssetLoader for AssetManifestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let manifest: AssetManifest = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(manifest));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["manifest.ron"]
+    }
+}
+
+/// Tracks every handle requested by the manifest, so loading can report progress and failures.
+#[derive(Resource, Default)]
+pub struct LoadingProgress {
+    manifest: Handle<AssetManifest>,
+    handles: Vec<(String, HandleUntyped)>,
+    pending_sound_banks: Vec<Handle<SoundBank>>,
+    pending_themes: Vec<Handle<Theme>>,
+    pending_peg_types: Vec<Handle<PegTypeDefs>>,
+    pending_packs: Vec<Handle<LevelPack>>,
+    pending_levels: Vec<Handle<Level>>,
+    pub errors: Vec<String>,
+}
+
+impl LoadingProgress {
+    /// Fraction of tracked assets that finished loading, from 0.0 to 1.0.
+    pub fn progress(&self, asset_server: &AssetServer) -> f32 {
+        if self.handles.is_empty() {
+            return 0.0;
+        }
+        let done = self
+            .handles
+            .iter()
+            .filter(|(_, h)| {
+                matches!(
+                    asset_server.get_load_state(h),
+                    LoadState::Loaded | LoadState::Failed
+                )
+            })
+            .count();
+        done as f32 / self.handles.len() as f32
+    }
+
+    fn track<T: Asset>(&mut self, path: &str, handle: &Handle<T>) {
+        self.handles
+            .push((path.to_string(), handle.clone_untyped()));
+    }
+}
+
+/// Loads assets listed in the manifest and records ones that can't be found in it.
+struct ManifestAssets<'a> {
+    manifest: &'a AssetManifest,
+    asset_server: &'a AssetServer,
+    progress: &'a mut LoadingProgress,
+}
+
+impl ManifestAssets<'_> {
+    fn load<T: Asset>(
+        &mut self,
+        group: &str,
+        kind: fn(&AssetGroup) -> &HashMap<String, String>,
+        name: &str,
+    ) -> Handle<T> {
+        let manifest = self.manifest;
+        let path = manifest.groups.get(group).and_then(|g| kind(g).get(name));
+        let Some(path) = path else {
+            self.progress
+                .errors
+                .push(format!("{group}/{name} is missing from {MANIFEST_PATH}"));
+            return Handle::default();
+        };
+        let handle = self.asset_server.load(path.as_str());
+        self.progress.track(path, &handle);
+        handle
+    }
+
+    fn peg_types(&mut self, group: &str, name: &str) -> Handle<PegTypeDefs> {
+        let handle = self.load(group, |g| &g.peg_types, name);
+        self.progress.pending_peg_types.push(handle.clone());
+        handle
+    }
+
+    /// Loads every theme of the group, sorted by name.
+    fn themes(&mut self, group: &str) -> Vec<(String, Handle<Theme>)> {
+        let manifest = self.manifest;
+        let mut names: Vec<_> = manifest
+            .groups
+            .get(group)
+            .map(|g| g.themes.keys().collect())
+            .unwrap_or_default();
+        if names.is_empty() {
+            self.progress
+                .errors
+                .push(format!("{group} has no themes in {MANIFEST_PATH}"));
+        }
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| {
+                let handle: Handle<Theme> = self.load(group, |g| &g.themes, name);
+                self.progress.pending_themes.push(handle.clone());
+                (name.clone(), handle)
+            })
+            .collect()
+    }
+
+    /// Loads every level pack in the packs directory and the ones listed in the group,
+    /// sorted by id.
+    fn packs(&mut self, group: &str) -> Vec<(String, Handle<LevelPack>)> {
+        let mut paths: Vec<String> = self
+            .manifest
+            .groups
+            .get(group)
+            .map(|g| g.packs.values().cloned().collect())
+            .unwrap_or_default();
+        paths.extend(self.pack_files());
+        let mut packs: BTreeMap<String, String> = BTreeMap::new();
+        for path in paths {
+            let Some(id) = pack_id(&path) else {
+                warn!("{path} is not in a directory of {PACKS_DIR}, it is ignored");
+                continue;
+            };
+            match packs.get(&id) {
+                Some(other) if *other != path => {
+                    warn!("Pack {id} has more than one pack file, {path} is ignored");
+                }
+                _ => {
+                    packs.insert(id, path);
+                }
+            }
+        }
+        packs
+            .into_iter()
+            .map(|(id, path)| {
+                let handle: Handle<LevelPack> = self.asset_server.load(path.as_str());
+                self.progress.track(&path, &handle);
+                self.progress.pending_packs.push(handle.clone());
+                (id, handle)
+            })
+            .collect()
+    }
+
+    /// Pack files found by listing the packs directory, which only works natively.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn pack_files(&self) -> Vec<String> {
+        let handles = match self.asset_server.load_folder(PACKS_DIR) {
+            Ok(handles) => handles,
+            Err(e) => {
+                warn!("Failed to list {PACKS_DIR}: {e}");
+                return Vec::new();
+            }
+        };
+        handles
+            .iter()
+            .filter_map(|h| self.asset_server.get_handle_path(h))
+            .map(|p| p.path().to_string_lossy().replace('\\', "/"))
+            .filter(|p| p.ends_with(".pack.ron"))
+            .collect()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn pack_files(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Name of the directory the pack file is in, if it is directly in a directory of the packs
+/// directory.
+fn pack_id(path: &str) -> Option<String> {
+    let path = std::path::Path::new(path);
+    let dir = path.parent()?;
+    if dir.parent()? != std::path::Path::new(PACKS_DIR) {
+        return None;
+    }
+    Some(dir.file_name()?.to_string_lossy().into_owned())
+}
+
 #[derive(Default)]
 pub struct PegAssets {
-    pub hit_sound: Arc<Vec<Handle<AudioSource>>>,
+    pub types: Handle<PegTypeDefs>,
     pub image: Handle<Image>,
     pub hit_image: Handle<Image>,
-    pub pop_sound: Handle<AudioSource>,
+    pub pop_sound: CollisionSound,
+    pub explode_sound: CollisionSound,
+    pub colors: HashMap<String, PegColors>,
 }
 
 #[derive(Default)]
 pub struct BallAssets {
-    pub hit_sound: Arc<Vec<Handle<AudioSource>>>,
+    pub hit_sound: CollisionSound,
     pub image: Handle<Image>,
 }
 
@@ -42,56 +267,163 @@ pub struct GameAssets {
     pub peg: PegAssets,
     pub ball: BallAssets,
     pub launcher: LauncherAssets,
-    pub background_image: Handle<Image>,
+    pub sound_bank: Handle<SoundBank>,
+    /// Level being played.
+    pub level: Handle<Level>,
+    /// Level played outside of the campaign.
+    pub default_level: Handle<Level>,
+    pub background_image: Option<Handle<Image>>,
     pub normal_font: Handle<Font>,
+    pub wall_color: Color,
 }
 
-fn load_assets(asset_server: Res<AssetServer>, mut assets: ResMut<GameAssets>) {
-    assets.peg.hit_sound = Arc::new(
-        vec![
-            "sfx/peg/impactGlass_medium_000.ogg",
-            "sfx/peg/impactGlass_medium_001.ogg",
-            "sfx/peg/impactGlass_medium_002.ogg",
-            "sfx/peg/impactGlass_medium_003.ogg",
-            "sfx/peg/impactGlass_medium_004.ogg",
-        ]
-        .into_iter()
-        .map(|s| asset_server.load(s))
-        .collect(),
-    );
-    assets.peg.pop_sound = asset_server.load("sfx/pop.ogg");
-
-    assets.peg.image = asset_server.load("sprites/peg/normal.png");
-    assets.peg.hit_image = asset_server.load("sprites/peg/hit.png");
-
-    assets.ball.hit_sound = Arc::new(
-        vec![
-            "sfx/ball/impactSoft_heavy_001.ogg",
-            "sfx/ball/impactSoft_heavy_002.ogg",
-            "sfx/ball/impactSoft_heavy_003.ogg",
-            "sfx/ball/impactSoft_heavy_004.ogg",
-        ]
-        .into_iter()
-        .map(|s| asset_server.load(s))
-        .collect(),
-    );
+fn load_manifest(asset_server: Res<AssetServer>, mut progress: ResMut<LoadingProgress>) {
+    *progress = LoadingProgress {
+        manifest: asset_server.load(MANIFEST_PATH),
+        ..Default::default()
+    };
+}
+
+fn load_assets(
+    asset_server: Res<AssetServer>,
+    manifests: Res<Assets<AssetManifest>>,
+    mut progress: ResMut<LoadingProgress>,
+    mut themes: ResMut<Themes>,
+    mut packs: ResMut<LevelPacks>,
+    mut assets: ResMut<GameAssets>,
+    mut manifest_loaded: Local<bool>,
+) {
+    if *manifest_loaded {
+        return;
+    }
+    if let LoadState::Failed = asset_server.get_load_state(&progress.manifest) {
+        progress.errors.push(MANIFEST_PATH.to_string());
+        *manifest_loaded = true;
+        return;
+    }
+    let Some(manifest) = manifests.get(&progress.manifest) else {return};
+    *manifest_loaded = true;
 
-    assets.ball.image = asset_server.load("sprites/ball.png");
-    assets.launcher.image = asset_server.load("sprites/launcher.png");
-    assets.background_image = asset_server.load("sprites/background.png");
-    assets.normal_font = asset_server.load("fonts/NotoSans.ttf");
+    let mut loader = ManifestAssets {
+        manifest,
+        asset_server: &asset_server,
+        progress: &mut *progress,
+    };
+    themes.themes = loader.themes("themes");
+    assets.peg.types = loader.peg_types("pegs", "default");
+    assets.default_level = loader.load("levels", |g| &g.levels, "default");
+    assets.level = assets.default_level.clone();
+    progress.pending_levels.push(assets.default_level.clone());
+    packs.packs = loader.packs("packs");
 }
 
 fn check_load_status(
     asset_server: Res<AssetServer>,
-    assets: Res<GameAssets>,
+    sound_banks: Res<Assets<SoundBank>>,
+    theme_assets: Res<Assets<Theme>>,
+    peg_type_defs: Res<Assets<PegTypeDefs>>,
+    pack_assets: Res<Assets<LevelPack>>,
+    levels: Res<Assets<Level>>,
+    mut progress: ResMut<LoadingProgress>,
     state: Res<State<GameState>>,
     mut next_state: ResMut<NextState<GameState>>,
 ) {
-    let s = asset_server.get_load_state(&assets.background_image);
-    match s {
-        LoadState::Loaded => next_state.set(state.next()),
-        LoadState::Failed => panic!("Failed to load"),
-        _ => (),
+    // Assets referenced by themes, peg types, sound banks, packs and levels are only known once
+    // those are loaded
+    for handle in std::mem::take(&mut progress.pending_themes) {
+        let load_state = asset_server.get_load_state(&handle);
+        if let Some(theme) = theme_assets.get(&handle) {
+            for image in theme.images() {
+                track_dependency(&mut progress, &asset_server, image);
+            }
+            track_dependency(&mut progress, &asset_server, &theme.font);
+            track_dependency(&mut progress, &asset_server, &theme.sound_bank);
+            progress.pending_sound_banks.push(theme.sound_bank.clone());
+        } else if let LoadState::Loading | LoadState::Loaded = load_state {
+            progress.pending_themes.push(handle);
+        }
+    }
+    for handle in std::mem::take(&mut progress.pending_peg_types) {
+        let load_state = asset_server.get_load_state(&handle);
+        if let Some(defs) = peg_type_defs.get(&handle) {
+            for image in defs.images() {
+                track_dependency(&mut progress, &asset_server, image);
+            }
+        } else if let LoadState::Loading | LoadState::Loaded = load_state {
+            progress.pending_peg_types.push(handle);
+        }
+    }
+    for handle in std::mem::take(&mut progress.pending_packs) {
+        let load_state = asset_server.get_load_state(&handle);
+        if let Some(pack) = pack_assets.get(&handle) {
+            for level in pack.levels.iter() {
+                track_dependency(&mut progress, &asset_server, &level.level);
+                progress.pending_levels.push(level.level.clone());
+            }
+        } else if let LoadState::Loading | LoadState::Loaded = load_state {
+            progress.pending_packs.push(handle);
+        }
+    }
+    for handle in std::mem::take(&mut progress.pending_levels) {
+        let load_state = asset_server.get_load_state(&handle);
+        if let Some(level) = levels.get(&handle) {
+            if let Some(music) = &level.music {
+                let track: Handle<AudioSource> = asset_server.load(music.as_str());
+                progress.track(music, &track);
+            }
+        } else if let LoadState::Loading | LoadState::Loaded = load_state {
+            progress.pending_levels.push(handle);
+        }
     }
+    for handle in std::mem::take(&mut progress.pending_sound_banks) {
+        let load_state = asset_server.get_load_state(&handle);
+        if let Some(bank) = sound_banks.get(&handle) {
+            for clip in bank.clips() {
+                track_dependency(&mut progress, &asset_server, clip);
+            }
+        } else if let LoadState::Loading | LoadState::Loaded = load_state {
+            progress.pending_sound_banks.push(handle);
+        }
+    }
+
+    // Manifest itself is still loading
+    if progress.handles.is_empty() && progress.errors.is_empty() {
+        return;
+    }
+
+    let mut all_done = true;
+    let mut failed = Vec::new();
+    for (path, handle) in progress.handles.iter() {
+        match asset_server.get_load_state(handle) {
+            LoadState::Loaded => (),
+            LoadState::Failed => failed.push(path.clone()),
+            _ => all_done = false,
+        }
+    }
+    let pending = !progress.pending_sound_banks.is_empty()
+        || !progress.pending_themes.is_empty()
+        || !progress.pending_peg_types.is_empty()
+        || !progress.pending_packs.is_empty()
+        || !progress.pending_levels.is_empty();
+    if !all_done || pending {
+        return;
+    }
+    progress.errors.extend(failed);
+    if progress.errors.is_empty() {
+        next_state.set(state.next());
+    } else {
+        next_state.set(GameState::LoadingFailed);
+    }
+}
+
+fn track_dependency<T: Asset>(
+    progress: &mut LoadingProgress,
+    asset_server: &AssetServer,
+    handle: &Handle<T>,
+) {
+    let path = asset_server.get_handle_path(handle).map_or_else(
+        || "<unknown>".to_string(),
+        |p| p.path().display().to_string(),
+    );
+    progress.track(&path, handle);
 }
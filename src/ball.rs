@@ -6,7 +6,7 @@ use bevy_rapier2d::prelude::*;
 
 use crate::common::{GameState, InGameState};
 use crate::peg::PegDespawnEvent;
-use crate::sounds::{CollisionSound, SoundType};
+use crate::sounds::CollisionSound;
 use crate::{assets::GameAssets, PLAYER_BALL_RADIUS};
 use crate::{ARENA_POS, ARENA_SIZE};
 
@@ -22,7 +22,8 @@ impl Plugin for BallPlugin {
             (ball_despawn_system, unstuck_ball)
                 .run_if(in_state(InGameState::Ball))
                 .run_if(in_state(GameState::InGame)),
-        );
+        )
+        .add_systems(OnExit(GameState::InGame), despawn_balls);
     }
 }
 
@@ -91,11 +92,7 @@ impl BallBundle {
             visibility: Default::default(),
             computed_visibility: Default::default(),
 
-            collision_sound: CollisionSound {
-                sound: SoundType::Random(game_assets.ball.hit_sound.clone()),
-                volume: 0.5,
-                ..Default::default()
-            },
+            collision_sound: game_assets.ball.hit_sound.clone(),
             name: Name::new("Ball"),
             ball: Ball,
         }
@@ -118,6 +115,12 @@ fn ball_despawn_system(mut commands: Commands, balls: Query<(Entity, &Transform)
     }
 }
 
+fn despawn_balls(mut commands: Commands, balls: Query<Entity, With<Ball>>) {
+    for entity in balls.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
 // Idea: run this system in a physics simulation that is in the future.
 // That will allow us to remove blocks on which the ball would've stuck
 // without making the player wait.
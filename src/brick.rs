@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::{Fill, GeometryBuilder, ShapeBundle};
+use bevy_prototype_lyon::shapes::Polygon;
+use bevy_rapier2d::prelude::*;
+use reggle_path::{Path, PathMode, SegmentType};
+use serde::{Deserialize, Serialize};
+
+use crate::peg::Peg;
+use crate::peg_type::{PegShape, PegTypeRegistry};
+use crate::sounds::CollisionSound;
+
+/// Run of bricks laid along a path, as it is written in level files.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BrickRunDef {
+    pub points: Vec<(f32, f32)>,
+    #[serde(default)]
+    pub segment_type: SegmentType,
+    #[serde(default)]
+    pub looped: bool,
+    pub count: usize,
+    /// Space left between neighboring bricks.
+    #[serde(default = "default_gap")]
+    pub gap: f32,
+    #[serde(default = "default_brick_type")]
+    pub peg_type: String,
+}
+
+fn default_gap() -> f32 {
+    2.0
+}
+
+fn default_brick_type() -> String {
+    "brick".to_string()
+}
+
+/// Marks pegs drawn with their own wedge shape instead of a sprite.
+#[derive(Component, Default)]
+pub struct Brick;
+
+/// Brick shaped to fit a piece of a curve.
+pub struct BrickWedge {
+    pub center: Vec2,
+    /// Angle of the curve tangent at the middle of the brick.
+    pub angle: f32,
+    /// Corners relative to the center, in brick space.
+    pub corners: [Vec2; 4],
+}
+
+/// Splits curve into `count` wedge-shaped bricks.
+/// `sample` returns position and tangent of the curve at `t` from 0.0 to 1.0, where `t` is the
+/// fraction of the curve length, so every brick covers the same length of the curve.
+pub fn brick_wedges(
+    sample: impl Fn(f32) -> (Vec2, Vec2),
+    count: usize,
+    thickness: f32,
+    gap: f32,
+) -> Vec<BrickWedge> {
+    let step = 1.0 / count as f32;
+    let half_thickness = thickness / 2.0;
+    (0..count)
+        .map(|i| {
+            let t0 = i as f32 * step;
+            let (start, start_vel) = sample(t0);
+            let (end, end_vel) = sample(t0 + step);
+            let (_, mid_vel) = sample(t0 + step / 2.0);
+            let (start_dir, end_dir) = (start_vel.normalize_or_zero(), end_vel.normalize_or_zero());
+            let start = start + start_dir * gap / 2.0;
+            let end = end - end_dir * gap / 2.0;
+            let (start_normal, end_normal) = (start_dir.perp(), end_dir.perp());
+
+            let world_corners = [
+                start - start_normal * half_thickness,
+                end - end_normal * half_thickness,
+                end + end_normal * half_thickness,
+                start + start_normal * half_thickness,
+            ];
+            let center = world_corners.iter().sum::<Vec2>() / 4.0;
+            let angle = mid_vel.y.atan2(mid_vel.x);
+            let rotation = Vec2::from_angle(-angle);
+            BrickWedge {
+                center,
+                angle,
+                corners: world_corners.map(|c| rotation.rotate(c - center)),
+            }
+        })
+        .collect()
+}
+
+pub fn brick_wedges_along_path(
+    path: &Path,
+    count: usize,
+    thickness: f32,
+    gap: f32,
+) -> Vec<BrickWedge> {
+    let length = path.length();
+    brick_wedges(
+        |t| path.get_world_pos_and_vel(path.t_at_length(t * length)),
+        count,
+        thickness,
+        gap,
+    )
+}
+
+#[derive(Bundle)]
+pub struct BrickBundle {
+    #[bundle()]
+    pub shape: ShapeBundle,
+    pub fill: Fill,
+    pub collider: Collider,
+    pub name: Name,
+    pub collision_sound: CollisionSound,
+    pub peg: Peg,
+    pub body: RigidBody,
+    pub brick: Brick,
+}
+
+impl BrickBundle {
+    pub fn new(wedge: &BrickWedge, peg_type: &str) -> Option<Self> {
+        let collider = Collider::convex_polygon(&wedge.corners)?;
+        let polygon = Polygon {
+            points: wedge.corners.to_vec(),
+            closed: true,
+        };
+        Some(Self {
+            shape: ShapeBundle {
+                path: GeometryBuilder::build_as(&polygon),
+                transform: Transform::from_translation(wedge.center.extend(0.0))
+                    .with_rotation(Quat::from_rotation_z(wedge.angle)),
+                ..Default::default()
+            },
+            fill: Fill::color(Color::WHITE),
+            collider,
+            name: Name::new("Brick"),
+            collision_sound: Default::default(),
+            peg: Peg::new(peg_type),
+            body: RigidBody::Fixed,
+            brick: Brick,
+        })
+    }
+}
+
+/// Spawns bricks of the run and returns how many of them were spawned.
+pub fn spawn_brick_run(
+    commands: &mut Commands,
+    registry: &PegTypeRegistry,
+    run: &BrickRunDef,
+) -> usize {
+    let Some(peg_type) = registry.get(&run.peg_type) else {
+        warn!("Unknown brick peg type \"{}\"", run.peg_type);
+        return 0;
+    };
+    let PegShape::Brick { thickness } = peg_type.shape else {
+        warn!("Peg type \"{}\" is not a brick", run.peg_type);
+        return 0;
+    };
+    let points: Vec<_> = run.points.iter().map(|&p| Vec2::from(p)).collect();
+    let mode = if run.looped {
+        PathMode::Loop
+    } else {
+        PathMode::OnceStop
+    };
+    let path = Path::from_positions(&points, run.segment_type, mode);
+    if path.segment_count() == 0 {
+        return 0;
+    }
+    let bricks: Vec<_> = brick_wedges_along_path(&path, run.count, thickness, run.gap)
+        .iter()
+        .filter_map(|wedge| BrickBundle::new(wedge, &run.peg_type))
+        .collect();
+    let spawned = bricks.len();
+    commands.spawn_batch(bricks);
+    spawned
+}
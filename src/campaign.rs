@@ -0,0 +1,336 @@
+use std::collections::BTreeMap;
+
+use bevy::asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::GameAssets;
+use crate::common::{GameState, GameStats, InGameState};
+use crate::input::{GameAction, GameInput};
+use crate::level::{spawn_level, Level};
+
+pub struct CampaignPlugin;
+
+impl Plugin for CampaignPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<LevelPack>()
+            .init_asset_loader::<LevelPackLoader>()
+            .init_resource::<LevelPacks>()
+            .init_resource::<Campaign>()
+            .add_systems(OnEnter(GameState::InGame), start_level.before(spawn_level))
+            .add_systems(OnExit(GameState::InGame), reset_in_game_state)
+            .add_systems(
+                OnEnter(InGameState::Launcher),
+                check_level_end.run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                Update,
+                leave_level
+                    .run_if(
+                        in_state(InGameState::Launcher).or_else(in_state(InGameState::Finished)),
+                    )
+                    .run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(
+                OnEnter(InGameState::Finished),
+                finish_level.run_if(in_state(GameState::InGame)),
+            )
+            .add_systems(OnExit(InGameState::Finished), despawn_results_ui);
+    }
+}
+
+/// Level of a pack, with everything the pack says about it.
+pub struct PackLevel {
+    /// Path of the level file relative to the pack, identifies the level in saved progress.
+    pub key: String,
+    pub name: String,
+    pub author: String,
+    pub par_score: usize,
+    /// Balls the player gets for the level.
+    pub balls: usize,
+    pub level: Handle<Level>,
+}
+
+/// Ordered list of levels loaded from a `*.pack.ron` file.
+#[derive(TypeUuid, TypePath)]
+#[uuid = "6a2f8c41-3d5e-4b7a-9f1c-0e8d7b6a5c43"]
+pub struct LevelPack {
+    pub name: String,
+    pub author: String,
+    pub levels: Vec<PackLevel>,
+}
+
+#[derive(Deserialize)]
+struct PackLevelDef {
+    file: String,
+    name: String,
+    /// Pack author is used if left out.
+    #[serde(default)]
+    author: Option<String>,
+    par_score: usize,
+    balls: usize,
+}
+
+/// [`LevelPack`] as it is written in `*.pack.ron` files.
+#[derive(Deserialize)]
+struct LevelPackDef {
+    name: String,
+    author: String,
+    levels: Vec<PackLevelDef>,
+}
+
+#[derive(Default)]
+pub struct LevelPackLoader;
+
+impl AssetLoader for LevelPackLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let def: LevelPackDef = ron::de::from_bytes(bytes)?;
+            // Level files are next to the pack file
+            let dir = load_context
+                .path()
+                .parent()
+                .map(|p| p.to_path_buf())
+                .unwrap_or_default();
+            let mut dependencies = Vec::new();
+            let levels = def
+                .levels
+                .into_iter()
+                .map(|level| {
+                    let asset_path = AssetPath::new(dir.join(&level.file), None);
+                    dependencies.push(asset_path.clone());
+                    PackLevel {
+                        level: load_context.get_handle(asset_path),
+                        key: level.file,
+                        name: level.name,
+                        author: level.author.unwrap_or_else(|| def.author.clone()),
+                        par_score: level.par_score,
+                        balls: level.balls,
+                    }
+                })
+                .collect();
+            let pack = LevelPack {
+                name: def.name,
+                author: def.author,
+                levels,
+            };
+            load_context.set_default_asset(LoadedAsset::new(pack).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pack.ron"]
+    }
+}
+
+/// Every level pack, sorted by id. Id of a pack is the name of its directory in `packs`.
+#[derive(Resource, Default)]
+pub struct LevelPacks {
+    pub packs: Vec<(String, Handle<LevelPack>)>,
+}
+
+/// Pack level being played. Levels are played freely, without a ball limit, if unset.
+#[derive(Resource, Default)]
+pub struct Campaign {
+    pub current: Option<CampaignLevel>,
+}
+
+#[derive(Clone, Copy)]
+pub struct CampaignLevel {
+    /// Index in [`LevelPacks`].
+    pub pack: usize,
+    /// Index in [`LevelPack::levels`].
+    pub level: usize,
+}
+
+/// What the player achieved in one level.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct LevelRecord {
+    pub unlocked: bool,
+    pub cleared: bool,
+    /// Failed attempts included.
+    pub best_score: usize,
+    /// Fewest balls the level was cleared with.
+    pub fewest_balls: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct PackProgress {
+    /// Keyed by [`PackLevel::key`], levels that were never unlocked are left out.
+    pub levels: BTreeMap<String, LevelRecord>,
+}
+
+impl PackProgress {
+    pub fn level(&self, key: &str) -> LevelRecord {
+        self.levels.get(key).cloned().unwrap_or_default()
+    }
+
+    pub fn cleared_count(&self) -> usize {
+        self.levels.values().filter(|l| l.cleared).count()
+    }
+
+    /// Clearing a level unlocks the next one. Unlocks are kept if the pack is reordered later.
+    pub fn is_unlocked(&self, pack: &LevelPack, level: usize) -> bool {
+        level == 0
+            || self.level(&pack.levels[level].key).unlocked
+            || self.level(&pack.levels[level - 1].key).cleared
+    }
+}
+
+/// Campaign progress of the player, keyed by pack id.
+#[derive(Resource, Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct CampaignProgress {
+    pub packs: BTreeMap<String, PackProgress>,
+    /// Best score in free play.
+    pub free_play_best: usize,
+}
+
+impl CampaignProgress {
+    pub fn pack(&self, id: &str) -> PackProgress {
+        self.packs.get(id).cloned().unwrap_or_default()
+    }
+}
+
+/// Pack being played and index of the level in it, if they are loaded.
+fn current_level<'a>(
+    campaign: &Campaign,
+    packs: &'a LevelPacks,
+    pack_assets: &'a Assets<LevelPack>,
+) -> Option<(&'a String, &'a LevelPack, usize)> {
+    let current = campaign.current?;
+    let (id, handle) = packs.packs.get(current.pack)?;
+    let pack = pack_assets.get(handle)?;
+    pack.levels.get(current.level)?;
+    Some((id, pack, current.level))
+}
+
+fn start_level(
+    campaign: Res<Campaign>,
+    packs: Res<LevelPacks>,
+    pack_assets: Res<Assets<LevelPack>>,
+    mut game_stats: ResMut<GameStats>,
+) {
+    let level = current_level(&campaign, &packs, &pack_assets);
+    *game_stats = GameStats {
+        balls_left: level.map(|(_, pack, level)| pack.levels[level].balls),
+        ..Default::default()
+    };
+}
+
+/// Starts from the launcher the next time a level is played.
+fn reset_in_game_state(mut commands: Commands) {
+    commands.insert_resource(NextState(Some(InGameState::Launcher)));
+}
+
+/// Runs once the last shot is over.
+fn check_level_end(mut commands: Commands, game_stats: Res<GameStats>) {
+    if game_stats.target_pegs_left == 0 || game_stats.balls_left == Some(0) {
+        commands.insert_resource(NextState(Some(InGameState::Finished)));
+    }
+}
+
+fn leave_level(
+    mut commands: Commands,
+    input_state: Res<GameInput>,
+    state: Res<State<InGameState>>,
+) {
+    let finished = *state.get() == InGameState::Finished;
+    if input_state.just_active(GameAction::Back)
+        || finished && input_state.just_active(GameAction::Confirm)
+    {
+        commands.insert_resource(NextState(Some(GameState::Menu)));
+    }
+}
+
+#[derive(Component)]
+struct ResultsUi;
+
+fn finish_level(
+    mut commands: Commands,
+    game_stats: Res<GameStats>,
+    game_assets: Res<GameAssets>,
+    campaign: Res<Campaign>,
+    packs: Res<LevelPacks>,
+    pack_assets: Res<Assets<LevelPack>>,
+    mut progress: ResMut<CampaignProgress>,
+) {
+    let cleared = game_stats.target_pegs_left == 0;
+    let score = game_stats.player_score;
+    let mut lines = vec![
+        if cleared {
+            "Level cleared!"
+        } else {
+            "Out of balls"
+        }
+        .to_string(),
+        format!("Score: {score}"),
+    ];
+    if let Some((pack_id, pack, index)) = current_level(&campaign, &packs, &pack_assets) {
+        let level = &pack.levels[index];
+        lines[1] += &format!(" (par {})", level.par_score);
+        lines.push(format!("Balls used: {}", game_stats.balls_used));
+        let pack_progress = progress.packs.entry(pack_id.clone()).or_default();
+        let record = pack_progress.levels.entry(level.key.clone()).or_default();
+        record.unlocked = true;
+        record.best_score = record.best_score.max(score);
+        if cleared {
+            record.cleared = true;
+            let balls = record.fewest_balls.unwrap_or(usize::MAX);
+            record.fewest_balls = Some(balls.min(game_stats.balls_used));
+            if let Some(next) = pack.levels.get(index + 1) {
+                let next = pack_progress.levels.entry(next.key.clone()).or_default();
+                next.unlocked = true;
+            }
+        }
+    } else {
+        progress.free_play_best = progress.free_play_best.max(score);
+        lines[1] += &format!(" (best {})", progress.free_play_best);
+    }
+    lines.push("\nPress Enter to continue".to_string());
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            ResultsUi,
+        ))
+        .with_children(|cb| {
+            cb.spawn(TextBundle {
+                text: Text::from_section(
+                    lines.join("\n"),
+                    TextStyle {
+                        font: game_assets.normal_font.clone(),
+                        font_size: 42.0,
+                        ..Default::default()
+                    },
+                )
+                .with_alignment(TextAlignment::Center),
+                ..Default::default()
+            });
+        });
+}
+
+fn despawn_results_ui(mut commands: Commands, results_ui: Query<Entity, With<ResultsUi>>) {
+    for e in results_ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
@@ -3,10 +3,19 @@ use bevy::prelude::*;
 #[derive(Component)]
 pub struct MainCamera;
 
+#[derive(Component)]
+pub struct Background;
+
+#[derive(Component)]
+pub struct Wall;
+
 #[derive(Reflect, Resource)]
 pub struct GameStats {
     pub player_score: usize,
     pub target_pegs_left: usize,
+    /// Balls the player can still shoot, unlimited if unset.
+    pub balls_left: Option<usize>,
+    pub balls_used: usize,
 }
 
 impl Default for GameStats {
@@ -14,6 +23,8 @@ impl Default for GameStats {
         Self {
             player_score: 0,
             target_pegs_left: 20,
+            balls_left: None,
+            balls_used: 0,
         }
     }
 }
@@ -22,6 +33,7 @@ impl Default for GameStats {
 pub enum GameState {
     #[default]
     LoadingAssets,
+    LoadingFailed,
     Menu,
     InGame,
 }
@@ -30,7 +42,8 @@ impl GameState {
     pub fn next(&self) -> Self {
         use GameState::*;
         match *self {
-            LoadingAssets => InGame,
+            LoadingAssets => Menu,
+            LoadingFailed => LoadingFailed,
             Menu => InGame,
             InGame => InGame,
         }
@@ -43,4 +56,8 @@ pub enum InGameState {
     Launcher,
     Ball,
     Cleanup,
+    /// Layout is edited with the mouse instead of being played.
+    Editor,
+    /// Level was cleared or ran out of balls, results are shown.
+    Finished,
 }
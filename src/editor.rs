@@ -0,0 +1,805 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::Collider;
+
+use crate::assets::GameAssets;
+use crate::brick::Brick;
+use crate::common::{GameState, GameStats, InGameState, Wall};
+use crate::input::{GameAction, GameInput};
+use crate::level::{spawn_wall, Level, WallDef};
+use crate::path::{
+    spawn_path, Path, PathAgent, PathAgentDef, PathDef, PathMode, PathPoint, PathPointDef,
+};
+use crate::peg::{grid_pegs, Peg, PegDef, PegState};
+use crate::peg_type::{PegShape, PegTypeRegistry};
+use crate::{ARENA_POS, ARENA_SIZE, PEG_RADIUS};
+
+/// Half of the grid layout spacing, so snapped pegs line up with it.
+const SNAP_STEP: f32 = PEG_RADIUS * 2.5;
+/// Degrees a single rotate key press turns the selection by.
+const ROTATION_STEP: f32 = 15.0;
+const NEW_WALL_HALF_SIZE: (f32, f32) = (50.0, 10.0);
+const NEW_PATH_SPEED: f32 = 100.0;
+const POINT_PICK_RADIUS: f32 = 8.0;
+/// How close to a path a click has to be to attach an agent to it.
+const AGENT_PICK_DISTANCE: f32 = 30.0;
+/// Cursor has to move this far before a click turns into a drag.
+const DRAG_THRESHOLD: f32 = 2.0;
+const MAX_HISTORY: usize = 100;
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Editor>()
+            .init_resource::<OpenEditor>()
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (load_layout, open_editor_on_start),
+            )
+            .add_systems(Update, toggle_editor.run_if(in_state(GameState::InGame)))
+            .add_systems(OnEnter(InGameState::Editor), enter_editor)
+            .add_systems(OnExit(InGameState::Editor), exit_editor)
+            .add_systems(
+                Update,
+                (
+                    respawn_layout,
+                    forget_reloaded_level,
+                    editor_keys,
+                    editor_mouse,
+                    editor_history,
+                    draw_editor_gizmos,
+                    update_editor_ui,
+                )
+                    .chain()
+                    .run_if(in_state(InGameState::Editor))
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+/// Set to start the next level in the editor instead of at the launcher.
+#[derive(Resource, Default)]
+pub struct OpenEditor(pub bool);
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+enum EditorTool {
+    #[default]
+    Peg,
+    Wall,
+    Path,
+    Agent,
+}
+
+/// Part of the level the editor can change.
+#[derive(Default, Clone)]
+struct Layout {
+    walls: Vec<WallDef>,
+    pegs: Vec<PegDef>,
+    paths: Vec<PathDef>,
+}
+
+impl Layout {
+    fn from_level(level: &Level) -> Self {
+        // Random grid of a level without pegs is laid out like any other pegs
+        let pegs = if level.pegs.is_empty() {
+            grid_pegs()
+                .iter()
+                .map(|p| PegDef {
+                    position: p.sprite_bundle.transform.translation.truncate().into(),
+                    rotation: 0.0,
+                    peg_type: p.peg.peg_type.clone(),
+                })
+                .collect()
+        } else {
+            level.pegs.clone()
+        };
+        Self {
+            walls: level.walls.clone(),
+            pegs,
+            paths: level.paths.clone(),
+        }
+    }
+
+    fn position_mut(&mut self, item: LayoutItem) -> Option<&mut (f32, f32)> {
+        match item {
+            LayoutItem::Wall(i) => Some(&mut self.walls.get_mut(i)?.position),
+            LayoutItem::Peg(i) => Some(&mut self.pegs.get_mut(i)?.position),
+            LayoutItem::PathPoint(i, j) => {
+                Some(&mut self.paths.get_mut(i)?.points.get_mut(j)?.position)
+            }
+            LayoutItem::Path(_) | LayoutItem::PathAgent(..) => None,
+        }
+    }
+
+    /// Counterclockwise, in degrees.
+    fn rotation_mut(&mut self, item: LayoutItem) -> Option<&mut f32> {
+        match item {
+            LayoutItem::Wall(i) => Some(&mut self.walls.get_mut(i)?.rotation),
+            LayoutItem::Peg(i) => Some(&mut self.pegs.get_mut(i)?.rotation),
+            _ => None,
+        }
+    }
+
+    fn peg_type_mut(&mut self, item: LayoutItem) -> Option<&mut String> {
+        match item {
+            LayoutItem::Peg(i) => Some(&mut self.pegs.get_mut(i)?.peg_type),
+            LayoutItem::PathAgent(i, j) => {
+                Some(&mut self.paths.get_mut(i)?.agents.get_mut(j)?.peg_type)
+            }
+            _ => None,
+        }
+    }
+
+    fn remove(&mut self, item: LayoutItem) {
+        match item {
+            LayoutItem::Wall(i) => {
+                self.walls.remove(i);
+            }
+            LayoutItem::Peg(i) => {
+                self.pegs.remove(i);
+            }
+            LayoutItem::Path(i) => {
+                self.paths.remove(i);
+            }
+            LayoutItem::PathPoint(i, j) => {
+                self.paths[i].points.remove(j);
+                // Path goes away together with its last point
+                if self.paths[i].points.is_empty() {
+                    self.paths.remove(i);
+                }
+            }
+            LayoutItem::PathAgent(i, j) => {
+                self.paths[i].agents.remove(j);
+            }
+        }
+    }
+}
+
+/// Item of the [`Layout`] an entity shows, as indices into it.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+enum LayoutItem {
+    Wall(usize),
+    Peg(usize),
+    Path(usize),
+    /// Path index, then point index.
+    PathPoint(usize, usize),
+    /// Path index, then agent index.
+    PathAgent(usize, usize),
+}
+
+impl LayoutItem {
+    fn path_index(self) -> Option<usize> {
+        match self {
+            LayoutItem::Path(i) | LayoutItem::PathPoint(i, _) | LayoutItem::PathAgent(i, _) => {
+                Some(i)
+            }
+            LayoutItem::Wall(_) | LayoutItem::Peg(_) => None,
+        }
+    }
+}
+
+struct Drag {
+    item: LayoutItem,
+    /// From the cursor to the dragged item.
+    offset: Vec2,
+    start: Vec2,
+    moved: bool,
+}
+
+#[derive(Resource)]
+struct Editor {
+    tool: EditorTool,
+    /// Peg type placed by the peg and agent tools.
+    peg_type: String,
+    snap: bool,
+    selected: Option<LayoutItem>,
+    drag: Option<Drag>,
+    /// Layout of the current level as it is edited and saved, entities only show it.
+    layout: Layout,
+    /// Layout has to be respawned to be shown as it is.
+    respawn: bool,
+    undo: Vec<Layout>,
+    redo: Vec<Layout>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            tool: Default::default(),
+            peg_type: "round".to_string(),
+            snap: true,
+            selected: None,
+            drag: None,
+            layout: Default::default(),
+            respawn: false,
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+}
+
+impl Editor {
+    /// Remembers the layout before an edit, so the edit can be undone.
+    fn checkpoint(&mut self) {
+        self.undo.push(self.layout.clone());
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// Applies an edit that returns whether it changed anything, and shows the result.
+    fn edit(&mut self, edit: impl FnOnce(&mut Layout) -> bool) {
+        let mut edited = self.layout.clone();
+        if edit(&mut edited) {
+            self.checkpoint();
+            self.layout = edited;
+            self.respawn = true;
+        }
+    }
+
+    fn snap(&self, pos: Vec2) -> Vec2 {
+        if self.snap {
+            (pos / SNAP_STEP).round() * SNAP_STEP
+        } else {
+            pos
+        }
+    }
+}
+
+#[derive(Component)]
+struct EditorUi;
+
+/// Entities the layout is shown with.
+#[derive(SystemParam)]
+struct LayoutView<'w, 's> {
+    walls: Query<'w, 's, Entity, With<Wall>>,
+    pegs: Query<'w, 's, Entity, (With<Peg>, Without<Parent>, Without<Brick>)>,
+    paths: Query<'w, 's, Entity, With<Path>>,
+    items: Query<'w, 's, (Entity, &'static LayoutItem)>,
+}
+
+impl LayoutView<'_, '_> {
+    fn entity(&self, item: LayoutItem) -> Option<Entity> {
+        self.items.iter().find(|(_, i)| **i == item).map(|(e, _)| e)
+    }
+
+    /// Despawns everything the layout is made of, whether it was spawned by the editor or not.
+    fn clear(&self, commands: &mut Commands) {
+        let walls = self.walls.iter();
+        let pegs = self.pegs.iter();
+        let paths = self.paths.iter();
+        for entity in walls.chain(pegs).chain(paths) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn spawn_layout(commands: &mut Commands, layout: &Layout, wall_color: Color) {
+    for (i, wall) in layout.walls.iter().enumerate() {
+        let wall = spawn_wall(commands, wall, wall_color);
+        commands.entity(wall).insert(LayoutItem::Wall(i));
+    }
+    let pegs = layout.pegs.iter().enumerate();
+    commands.spawn_batch(
+        pegs.map(|(i, p)| (p.bundle(), LayoutItem::Peg(i)))
+            .collect::<Vec<_>>(),
+    );
+    for (i, path) in layout.paths.iter().enumerate() {
+        // Points and agents are spawned here, so they can be tagged with their items
+        let bare = PathDef {
+            mode: path.mode,
+            move_speed: path.move_speed,
+            points: Vec::new(),
+            agents: Vec::new(),
+            tolerance: path.tolerance,
+        };
+        let entity = spawn_path(commands, &bare);
+        commands
+            .entity(entity)
+            .insert(LayoutItem::Path(i))
+            .with_children(|cb| {
+                for (j, point) in path.points.iter().enumerate() {
+                    cb.spawn((point.bundle(), LayoutItem::PathPoint(i, j)));
+                }
+                for (j, agent) in path.agents.iter().enumerate() {
+                    cb.spawn((agent.bundle(), LayoutItem::PathAgent(i, j)));
+                }
+            });
+    }
+}
+
+/// Entities that can be selected with the mouse. Only the ones showing the layout are, so
+/// bricks and pegs of rotators stay out of reach.
+#[derive(SystemParam)]
+struct Pickables<'w, 's> {
+    points: Query<'w, 's, (&'static LayoutItem, &'static GlobalTransform), With<PathPoint>>,
+    pegs: Query<'w, 's, (&'static LayoutItem, &'static GlobalTransform, &'static Peg)>,
+    walls: Query<
+        'w,
+        's,
+        (
+            &'static LayoutItem,
+            &'static GlobalTransform,
+            &'static Collider,
+        ),
+        With<Wall>,
+    >,
+    registry: Res<'w, PegTypeRegistry>,
+}
+
+impl Pickables<'_, '_> {
+    /// Item under `pos`, together with where it is.
+    fn pick(&self, pos: Vec2) -> Option<(LayoutItem, Vec2)> {
+        let local_pos = |tr: &GlobalTransform| {
+            let local = tr.affine().inverse().transform_point3(pos.extend(0.0));
+            local.truncate()
+        };
+        // Points are the smallest, so they win over whatever they are drawn on top of
+        let point = self
+            .points
+            .iter()
+            .find(|(_, tr)| tr.translation().truncate().distance(pos) < POINT_PICK_RADIUS);
+        let peg = || {
+            self.pegs.iter().find(|(_, tr, peg)| {
+                let Some(peg_type) = self.registry.get(&peg.peg_type) else {return false};
+                let local = local_pos(*tr);
+                let half_size = peg_type.shape.size() / 2.0;
+                match peg_type.shape {
+                    PegShape::Circle { .. } => local.length() <= half_size.x,
+                    _ => local.abs().cmple(half_size).all(),
+                }
+            })
+        };
+        let wall = || {
+            self.walls.iter().find(|(_, tr, collider)| {
+                let Some(cuboid) = collider.as_cuboid() else {return false};
+                local_pos(*tr).abs().cmple(cuboid.half_extents()).all()
+            })
+        };
+        point
+            .or_else(|| peg().map(|(item, tr, _)| (item, tr)))
+            .or_else(|| wall().map(|(item, tr, _)| (item, tr)))
+            .map(|(item, tr)| (*item, tr.translation().truncate()))
+    }
+}
+
+/// Peg types the peg tool can place, bricks only come in runs.
+fn placeable_peg_types(registry: &PegTypeRegistry) -> Vec<&str> {
+    let mut ids: Vec<_> = registry
+        .iter()
+        .filter(|(_, t)| !matches!(t.shape, PegShape::Brick { .. }))
+        .map(|(id, _)| id)
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+fn open_editor_on_start(mut commands: Commands, mut open_editor: ResMut<OpenEditor>) {
+    if std::mem::take(&mut open_editor.0) {
+        commands.insert_resource(NextState(Some(InGameState::Editor)));
+    }
+}
+
+/// F1 shortcut, the menu opens levels in the editor too.
+fn toggle_editor(
+    mut commands: Commands,
+    input_state: Res<GameInput>,
+    in_game_state: Res<State<InGameState>>,
+) {
+    if !input_state.just_active(GameAction::ToggleEditor) {
+        return;
+    }
+    // Balls in play have to finish first
+    let next = match in_game_state.get() {
+        InGameState::Launcher => InGameState::Editor,
+        InGameState::Editor => InGameState::Launcher,
+        _ => return,
+    };
+    commands.insert_resource(NextState(Some(next)));
+}
+
+/// Every level starts out with the layout from its file.
+fn load_layout(
+    mut editor: ResMut<Editor>,
+    game_assets: Res<GameAssets>,
+    levels: Res<Assets<Level>>,
+) {
+    editor.layout = levels
+        .get(&game_assets.level)
+        .map(Layout::from_level)
+        .unwrap_or_default();
+    editor.undo.clear();
+    editor.redo.clear();
+}
+
+fn enter_editor(mut commands: Commands, mut editor: ResMut<Editor>, game_assets: Res<GameAssets>) {
+    // Pegs cleared and agents moved while playing are put back where they were laid out
+    editor.respawn = true;
+    editor.selected = None;
+    editor.drag = None;
+    commands.spawn((
+        TextBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: game_assets.normal_font.clone(),
+                    font_size: 20.0,
+                    ..Default::default()
+                },
+            ),
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..Default::default()
+        },
+        EditorUi,
+    ));
+}
+
+fn exit_editor(
+    mut commands: Commands,
+    editor_ui: Query<Entity, With<EditorUi>>,
+    pegs: Query<&Peg>,
+    registry: Res<PegTypeRegistry>,
+    mut game_stats: ResMut<GameStats>,
+) {
+    for e in editor_ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    game_stats.target_pegs_left = pegs
+        .iter()
+        .filter(|p| p.state != PegState::Hit)
+        .filter(|p| registry.get(&p.peg_type).map_or(false, |t| t.is_target))
+        .count();
+}
+
+/// Runs first, so a level respawned from disk during the last frame is replaced as well.
+fn respawn_layout(
+    mut commands: Commands,
+    mut editor: ResMut<Editor>,
+    view: LayoutView,
+    game_assets: Res<GameAssets>,
+) {
+    if std::mem::take(&mut editor.respawn) {
+        view.clear(&mut commands);
+        spawn_layout(&mut commands, &editor.layout, game_assets.wall_color);
+    }
+}
+
+/// Level changed on disk replaces the layout, together with history of edits made to the old one.
+fn forget_reloaded_level(
+    mut level_events: EventReader<AssetEvent<Level>>,
+    mut editor: ResMut<Editor>,
+    game_assets: Res<GameAssets>,
+    levels: Res<Assets<Level>>,
+) {
+    let modified = level_events.iter().any(|e| match e {
+        AssetEvent::Modified { handle } => *handle == game_assets.level,
+        _ => false,
+    });
+    if !modified {
+        return;
+    }
+    let Some(level) = levels.get(&game_assets.level) else {return};
+    editor.layout = Layout::from_level(level);
+    editor.undo.clear();
+    editor.redo.clear();
+    editor.selected = None;
+    editor.drag = None;
+    editor.respawn = true;
+}
+
+fn editor_keys(
+    keys: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    registry: Res<PegTypeRegistry>,
+) {
+    for (key, tool) in [
+        (KeyCode::Key1, EditorTool::Peg),
+        (KeyCode::Key2, EditorTool::Wall),
+        (KeyCode::Key3, EditorTool::Path),
+        (KeyCode::Key4, EditorTool::Agent),
+    ] {
+        if keys.just_pressed(key) {
+            editor.tool = tool;
+        }
+    }
+    if keys.just_pressed(KeyCode::G) {
+        editor.snap = !editor.snap;
+    }
+    let step = match (keys.just_pressed(KeyCode::Q), keys.just_pressed(KeyCode::E)) {
+        (true, false) => Some(-1),
+        (false, true) => Some(1),
+        _ => None,
+    };
+    if let Some(step) = step {
+        let peg_types = placeable_peg_types(&registry);
+        if !peg_types.is_empty() {
+            let current = peg_types.iter().position(|&t| t == editor.peg_type);
+            let next = current.map_or(0, |i| {
+                (i as isize + step).rem_euclid(peg_types.len() as isize) as usize
+            });
+            editor.peg_type = peg_types[next].to_string();
+        }
+    }
+
+    let Some(selected) = editor.selected else {return};
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keys.just_pressed(KeyCode::R) {
+        let step = if shift { -ROTATION_STEP } else { ROTATION_STEP };
+        editor.edit(|layout| {
+            let Some(rotation) = layout.rotation_mut(selected) else {return false};
+            *rotation += step;
+            true
+        });
+    }
+    if keys.just_pressed(KeyCode::X) {
+        editor.edit(|layout| {
+            let Some(peg_type) = layout.peg_type_mut(selected) else {return false};
+            let toggled = match peg_type.strip_suffix("_target") {
+                Some(normal) => normal.to_string(),
+                None => format!("{peg_type}_target"),
+            };
+            if registry.get(&toggled).is_none() {
+                info!("Peg type \"{peg_type}\" has no target counterpart");
+                return false;
+            }
+            *peg_type = toggled;
+            true
+        });
+    }
+    if keys.just_pressed(KeyCode::L) {
+        editor.edit(|layout| {
+            let path = selected.path_index().and_then(|i| layout.paths.get_mut(i));
+            let Some(path) = path else {return false};
+            path.mode = match path.mode {
+                PathMode::Loop => PathMode::PingPong,
+                PathMode::PingPong => PathMode::OnceStop,
+                PathMode::OnceStop => PathMode::OnceDespawn,
+                PathMode::OnceDespawn => PathMode::Loop,
+            };
+            true
+        });
+    }
+    if keys.any_just_pressed([KeyCode::Delete, KeyCode::Back]) {
+        editor.edit(|layout| {
+            layout.remove(selected);
+            true
+        });
+        editor.selected = None;
+    }
+}
+
+fn editor_mouse(
+    input_state: Res<GameInput>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut editor: ResMut<Editor>,
+    pickables: Pickables,
+    view: LayoutView,
+    paths: Query<(&LayoutItem, &Path)>,
+    mut transforms: Query<&mut Transform>,
+    mut agents: Query<&mut PathAgent>,
+) {
+    let cursor = input_state.cursor_position;
+    if mouse_buttons.just_pressed(MouseButton::Right) {
+        editor.selected = None;
+    }
+    if mouse_buttons.just_released(MouseButton::Left) {
+        editor.drag = None;
+    }
+    // Paths are spawned at the origin, so their curves are where the layout says
+    let path_curve = |i: usize| {
+        paths
+            .iter()
+            .find(|(item, _)| **item == LayoutItem::Path(i))
+            .map(|(_, path)| path)
+    };
+
+    if mouse_buttons.just_pressed(MouseButton::Left) {
+        if let Some((item, pos)) = pickables.pick(cursor) {
+            editor.selected = Some(item);
+            editor.drag = Some(Drag {
+                item,
+                offset: pos - cursor,
+                start: cursor,
+                moved: false,
+            });
+            return;
+        }
+        let pos = editor.snap(cursor);
+        let selected_path = editor.selected.and_then(LayoutItem::path_index);
+        let peg_type = editor.peg_type.clone();
+        let item = match editor.tool {
+            EditorTool::Peg => {
+                let peg = PegDef {
+                    position: pos.into(),
+                    rotation: 0.0,
+                    peg_type,
+                };
+                editor.edit(|layout| {
+                    layout.pegs.push(peg);
+                    true
+                });
+                LayoutItem::Peg(editor.layout.pegs.len() - 1)
+            }
+            EditorTool::Wall => {
+                let wall = WallDef {
+                    position: pos.into(),
+                    half_size: NEW_WALL_HALF_SIZE,
+                    rotation: 0.0,
+                };
+                editor.edit(|layout| {
+                    layout.walls.push(wall);
+                    true
+                });
+                LayoutItem::Wall(editor.layout.walls.len() - 1)
+            }
+            // Clicks keep adding points to the selected path, until it is deselected
+            EditorTool::Path => match selected_path {
+                Some(i) => {
+                    editor.edit(|layout| {
+                        layout.paths[i].points.push(PathPointDef::new(pos));
+                        true
+                    });
+                    LayoutItem::PathPoint(i, editor.layout.paths[i].points.len() - 1)
+                }
+                None => {
+                    let path = PathDef {
+                        mode: PathMode::Loop,
+                        move_speed: NEW_PATH_SPEED,
+                        points: vec![PathPointDef::new(pos)],
+                        agents: Vec::new(),
+                        tolerance: None,
+                    };
+                    editor.edit(|layout| {
+                        layout.paths.push(path);
+                        true
+                    });
+                    LayoutItem::PathPoint(editor.layout.paths.len() - 1, 0)
+                }
+            },
+            EditorTool::Agent => {
+                let closest = (0..editor.layout.paths.len())
+                    .filter_map(|i| {
+                        let (t, distance) = path_curve(i)?.closest_point(cursor)?;
+                        Some((i, t, distance))
+                    })
+                    .filter(|&(_, _, distance)| distance < AGENT_PICK_DISTANCE)
+                    .min_by(|a, b| a.2.total_cmp(&b.2));
+                let Some((i, t, _)) = closest else {return};
+                let agent = PathAgentDef {
+                    t,
+                    peg_type,
+                    orientation: Some(0.0),
+                };
+                editor.edit(|layout| {
+                    layout.paths[i].agents.push(agent);
+                    true
+                });
+                LayoutItem::PathAgent(i, editor.layout.paths[i].agents.len() - 1)
+            }
+        };
+        editor.selected = Some(item);
+        return;
+    }
+
+    if !mouse_buttons.pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(mut drag) = editor.drag.take() else {return};
+    if !drag.moved && cursor.distance(drag.start) > DRAG_THRESHOLD {
+        editor.checkpoint();
+        drag.moved = true;
+    }
+    if drag.moved {
+        // Moved in place, respawning the whole layout every frame of a drag would be wasteful
+        let entity = view.entity(drag.item);
+        if let LayoutItem::PathAgent(i, j) = drag.item {
+            // Agents are moved by their path, so only their place on it is dragged
+            if let Some((t, _)) = path_curve(i).and_then(|path| path.closest_point(cursor)) {
+                editor.layout.paths[i].agents[j].t = t;
+                if let Some(mut agent) = entity.and_then(|e| agents.get_mut(e).ok()) {
+                    agent.cursor.t = t;
+                    agent.cursor.pause_left = 0.0;
+                }
+            }
+        } else {
+            let target = editor.snap(cursor + drag.offset);
+            if let Some(position) = editor.layout.position_mut(drag.item) {
+                *position = target.into();
+                if let Some(mut tr) = entity.and_then(|e| transforms.get_mut(e).ok()) {
+                    tr.translation.x = target.x;
+                    tr.translation.y = target.y;
+                }
+            }
+        }
+    }
+    editor.drag = Some(drag);
+}
+
+fn editor_history(
+    keys: Res<Input<KeyCode>>,
+    mut editor: ResMut<Editor>,
+    game_assets: Res<GameAssets>,
+    levels: Res<Assets<Level>>,
+    asset_server: Res<AssetServer>,
+) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let undo = !shift && keys.just_pressed(KeyCode::Z);
+    let redo = keys.just_pressed(KeyCode::Y) || (shift && keys.just_pressed(KeyCode::Z));
+    if undo || redo {
+        let editor = &mut *editor;
+        let (from, to) = if undo {
+            (&mut editor.undo, &mut editor.redo)
+        } else {
+            (&mut editor.redo, &mut editor.undo)
+        };
+        let Some(restored) = from.pop() else {return};
+        to.push(std::mem::replace(&mut editor.layout, restored));
+        editor.respawn = true;
+        editor.selected = None;
+        editor.drag = None;
+    }
+
+    if keys.just_pressed(KeyCode::S) {
+        let Some(path) = asset_server.get_handle_path(&game_assets.level) else {
+            warn!("Current level wasn't loaded from a file and can't be saved");
+            return;
+        };
+        // Parts the editor doesn't touch are written back as they were loaded
+        let mut level = levels.get(&game_assets.level).cloned().unwrap_or_default();
+        let Layout { walls, pegs, paths } = editor.layout.clone();
+        level.walls = walls;
+        level.pegs = pegs;
+        level.paths = paths;
+        level.save(path.path());
+    }
+}
+
+fn draw_editor_gizmos(
+    editor: Res<Editor>,
+    view: LayoutView,
+    transforms: Query<&GlobalTransform>,
+    mut gizmos: Gizmos,
+) {
+    if editor.snap {
+        let color = Color::rgba(1.0, 1.0, 1.0, 0.05);
+        let (min, max) = (ARENA_POS - ARENA_SIZE / 2.0, ARENA_POS + ARENA_SIZE / 2.0);
+        let mut x = (min.x / SNAP_STEP).ceil() * SNAP_STEP;
+        while x <= max.x {
+            gizmos.line_2d(Vec2::new(x, min.y), Vec2::new(x, max.y), color);
+            x += SNAP_STEP;
+        }
+        let mut y = (min.y / SNAP_STEP).ceil() * SNAP_STEP;
+        while y <= max.y {
+            gizmos.line_2d(Vec2::new(min.x, y), Vec2::new(max.x, y), color);
+            y += SNAP_STEP;
+        }
+    }
+    let selected = editor
+        .selected
+        .and_then(|item| view.entity(item))
+        .and_then(|e| transforms.get(e).ok());
+    if let Some(tr) = selected {
+        gizmos.circle_2d(tr.translation().truncate(), PEG_RADIUS * 1.5, Color::YELLOW);
+    }
+}
+
+fn update_editor_ui(editor: Res<Editor>, mut editor_ui: Query<&mut Text, With<EditorUi>>) {
+    let Ok(mut text) = editor_ui.get_single_mut() else {return};
+    let snap = if editor.snap { "on" } else { "off" };
+    text.sections[0].value = format!(
+        "EDITOR  tool: {:?}  peg: {}  snap: {snap}\n\
+        1-4 tool  Q/E peg type  G snap  R rotate  X target  L path mode  Del delete\n\
+        RMB deselect  Ctrl+Z undo  Ctrl+Y redo  Ctrl+S save  F1 play",
+        editor.tool, editor.peg_type
+    );
+}
@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::{Fill, GeometryBuilder, ShapeBundle, Stroke};
+use bevy_prototype_lyon::shapes::Circle;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ball::Ball;
+use crate::common::{GameState, InGameState};
+
+const BUMPER_COLOR: Color = Color::rgb(1.0, 0.8, 0.2);
+const PORTAL_COLOR: Color = Color::rgb(0.3, 0.6, 1.0);
+const ATTRACTOR_COLOR: Color = Color::rgba(0.3, 0.3, 1.0, 0.15);
+const REPULSOR_COLOR: Color = Color::rgba(1.0, 0.3, 0.3, 0.15);
+
+pub struct ElementsPlugin;
+
+impl Plugin for ElementsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (bumper_system, portal_system, gravity_well_system)
+                .run_if(in_state(InGameState::Ball))
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// Non-scoring level element as it is written in level files.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum ElementDef {
+    Bumper {
+        position: (f32, f32),
+        radius: f32,
+        #[serde(default = "default_bumper_restitution")]
+        restitution: f32,
+        /// Speed added to the ball away from the bumper center on every hit.
+        #[serde(default)]
+        boost: f32,
+    },
+    /// Pair of portals, a ball entering one of them comes out of the other.
+    Portals {
+        entry: (f32, f32),
+        exit: (f32, f32),
+        #[serde(default = "default_portal_radius")]
+        radius: f32,
+    },
+    /// Pulls balls towards its center, or pushes them away if strength is negative.
+    GravityWell {
+        position: (f32, f32),
+        radius: f32,
+        strength: f32,
+    },
+}
+
+fn default_bumper_restitution() -> f32 {
+    1.5
+}
+
+fn default_portal_radius() -> f32 {
+    20.0
+}
+
+/// Marks every entity spawned from an [`ElementDef`].
+#[derive(Component)]
+pub struct LevelElement;
+
+#[derive(Component, Clone, Copy)]
+pub struct Bumper {
+    pub boost: f32,
+}
+
+impl Bumper {
+    /// Velocity added to a ball at `ball_pos` hitting this bumper.
+    pub fn boost(&self, bumper_pos: Vec2, ball_pos: Vec2) -> Vec2 {
+        (ball_pos - bumper_pos).normalize_or_zero() * self.boost
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Portal {
+    pub exit: Entity,
+    pub exit_pos: Vec2,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct GravityWell {
+    pub radius: f32,
+    pub strength: f32,
+}
+
+impl GravityWell {
+    /// Acceleration applied to a ball at `ball_pos`, fading out towards the edge of the well.
+    pub fn acceleration(&self, well_pos: Vec2, ball_pos: Vec2) -> Vec2 {
+        let offset = well_pos - ball_pos;
+        let falloff = 1.0 - offset.length() / self.radius;
+        if falloff <= 0.0 {
+            return Vec2::ZERO;
+        }
+        offset.normalize_or_zero() * self.strength * falloff
+    }
+}
+
+/// Ball came out of this portal and can't use it again until it leaves it.
+#[derive(Component)]
+struct PortalCooldown(Entity);
+
+pub fn spawn_element(commands: &mut Commands, def: &ElementDef) {
+    match *def {
+        ElementDef::Bumper {
+            position,
+            radius,
+            restitution,
+            boost,
+        } => {
+            commands.spawn((
+                circle_shape(position.into(), radius),
+                Fill::color(BUMPER_COLOR),
+                RigidBody::Fixed,
+                Collider::ball(radius),
+                Restitution {
+                    coefficient: restitution,
+                    combine_rule: CoefficientCombineRule::Max,
+                },
+                Bumper { boost },
+                LevelElement,
+            ));
+        }
+        ElementDef::Portals {
+            entry,
+            exit,
+            radius,
+        } => {
+            let mut spawn_portal = |pos: Vec2| {
+                commands
+                    .spawn((
+                        circle_shape(pos, radius),
+                        Stroke::new(PORTAL_COLOR, 4.0),
+                        Collider::ball(radius),
+                        Sensor,
+                        LevelElement,
+                    ))
+                    .id()
+            };
+            let (entry, exit) = (Vec2::from(entry), Vec2::from(exit));
+            let (entry_e, exit_e) = (spawn_portal(entry), spawn_portal(exit));
+            commands.entity(entry_e).insert(Portal {
+                exit: exit_e,
+                exit_pos: exit,
+            });
+            commands.entity(exit_e).insert(Portal {
+                exit: entry_e,
+                exit_pos: entry,
+            });
+        }
+        ElementDef::GravityWell {
+            position,
+            radius,
+            strength,
+        } => {
+            let color = if strength >= 0.0 {
+                ATTRACTOR_COLOR
+            } else {
+                REPULSOR_COLOR
+            };
+            commands.spawn((
+                circle_shape(position.into(), radius),
+                Fill::color(color),
+                GravityWell { radius, strength },
+                LevelElement,
+            ));
+        }
+    }
+}
+
+fn circle_shape(position: Vec2, radius: f32) -> ShapeBundle {
+    let shape = Circle {
+        radius,
+        center: Vec2::ZERO,
+    };
+    ShapeBundle {
+        path: GeometryBuilder::build_as(&shape),
+        transform: Transform::from_translation(position.extend(-1.0)),
+        ..Default::default()
+    }
+}
+
+fn bumper_system(
+    mut collision_events: EventReader<CollisionEvent>,
+    bumpers: Query<(&Bumper, &GlobalTransform)>,
+    mut balls: Query<(&mut Velocity, &GlobalTransform), With<Ball>>,
+) {
+    for event in collision_events.iter() {
+        let CollisionEvent::Started(e1, e2, _) = event else {continue};
+        let (bumper_e, ball_e) = if bumpers.contains(*e1) {
+            (*e1, *e2)
+        } else {
+            (*e2, *e1)
+        };
+        let Ok((bumper, bumper_tr)) = bumpers.get(bumper_e) else {continue};
+        let Ok((mut vel, ball_tr)) = balls.get_mut(ball_e) else {continue};
+        vel.linvel += bumper.boost(
+            bumper_tr.translation().truncate(),
+            ball_tr.translation().truncate(),
+        );
+    }
+}
+
+fn portal_system(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    portals: Query<&Portal>,
+    mut balls: Query<(&mut Transform, Option<&PortalCooldown>), With<Ball>>,
+) {
+    for event in collision_events.iter() {
+        let (e1, e2, started) = match event {
+            CollisionEvent::Started(e1, e2, _) => (e1, e2, true),
+            CollisionEvent::Stopped(e1, e2, _) => (e1, e2, false),
+        };
+        let (portal_e, ball_e) = if portals.contains(*e1) {
+            (*e1, *e2)
+        } else {
+            (*e2, *e1)
+        };
+        let Ok(portal) = portals.get(portal_e) else {continue};
+        let Ok((mut tr, cooldown)) = balls.get_mut(ball_e) else {continue};
+        let on_cooldown = cooldown.map_or(false, |c| c.0 == portal_e);
+        if !started {
+            if on_cooldown {
+                commands.entity(ball_e).remove::<PortalCooldown>();
+            }
+            continue;
+        }
+        if on_cooldown {
+            continue;
+        }
+        // Velocity is left untouched, so the ball keeps going the same way out of the exit
+        tr.translation = portal.exit_pos.extend(tr.translation.z);
+        commands.entity(ball_e).insert(PortalCooldown(portal.exit));
+    }
+}
+
+fn gravity_well_system(
+    time: Res<Time>,
+    wells: Query<(&GravityWell, &GlobalTransform)>,
+    mut balls: Query<(&mut Velocity, &GlobalTransform), With<Ball>>,
+) {
+    for (mut vel, ball_tr) in balls.iter_mut() {
+        let ball_pos = ball_tr.translation().truncate();
+        for (well, well_tr) in wells.iter() {
+            let accel = well.acceleration(well_tr.translation().truncate(), ball_pos);
+            vel.linvel += accel * time.delta_seconds();
+        }
+    }
+}
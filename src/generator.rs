@@ -0,0 +1,402 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::math::Affine2;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+
+use crate::level::{arena_walls, Level};
+use crate::level_check::{level_pegs, unreachable_targets, PEG_TYPES_PATH};
+use crate::path::{PathAgentDef, PathDef, PathMode, PathPointDef};
+use crate::peg::{PegDef, GRID_PEG_TYPES};
+use crate::peg_type::{read_peg_shapes, PegTypeShape};
+use crate::{ARENA_POS, ARENA_SIZE, LAUNCHER_POS, PEG_RADIUS};
+
+/// Closest two static pegs can be, center to center.
+const PEG_SPACING: f32 = PEG_RADIUS * 4.0;
+/// Closest a static peg can be to the route of a moving one.
+const ROUTE_CLEARANCE: f32 = PEG_SPACING + PEG_RADIUS;
+/// Space kept free along the walls and below the launcher.
+const MARGIN: f32 = PEG_RADIUS * 3.0;
+/// Tries at placing a single peg or path before giving up on it.
+const PLACEMENT_TRIES: usize = 30;
+
+/// How copies of every generated piece are laid out.
+#[derive(Deserialize, Clone, Copy)]
+pub enum Symmetry {
+    None,
+    /// Mirrored between the left and right half of the arena.
+    Mirror,
+    /// Copies rotated around the middle of the play area.
+    Radial(u32),
+}
+
+/// Everything a generated level depends on. Same parameters always give the same level.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct GeneratorParams {
+    pub seed: u64,
+    /// Static pegs in the level, symmetric copies included.
+    pub peg_count: usize,
+    /// Share of the static pegs that are targets.
+    pub target_ratio: f32,
+    pub symmetry: Symmetry,
+    /// Tight groups of pegs.
+    pub clusters: usize,
+    /// Rows of pegs laid along circular arcs.
+    pub arcs: usize,
+    /// Loops with pegs moving along them, before symmetric copies.
+    pub moving_groups: usize,
+    /// Levels generated before giving up on getting one with every target reachable.
+    pub attempts: usize,
+}
+
+impl Default for GeneratorParams {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            peg_count: 80,
+            target_ratio: 0.2,
+            symmetry: Symmetry::Mirror,
+            clusters: 2,
+            arcs: 2,
+            moving_groups: 1,
+            attempts: 20,
+        }
+    }
+}
+
+/// Static pegs that are copies of each other, as `(position, rotation in radians)`.
+struct PegGroup {
+    pegs: Vec<(Vec2, f32)>,
+    peg_types: (&'static str, &'static str),
+}
+
+/// Level in the making.
+struct LevelBuilder<'a> {
+    params: &'a GeneratorParams,
+    rng: fastrand::Rng,
+    area: Rect,
+    /// Transforms mapping a piece onto all of its copies, identity first.
+    symmetries: Vec<Affine2>,
+    groups: Vec<PegGroup>,
+    /// Points along the routes of moving pegs, kept clear of static pegs.
+    routes: Vec<Vec2>,
+    paths: Vec<PathDef>,
+}
+
+impl<'a> LevelBuilder<'a> {
+    fn new(params: &'a GeneratorParams, rng: fastrand::Rng) -> Self {
+        let area = Rect::new(
+            ARENA_POS.x - ARENA_SIZE.x / 2.0 + MARGIN,
+            ARENA_POS.y - ARENA_SIZE.y / 2.0 + MARGIN,
+            ARENA_POS.x + ARENA_SIZE.x / 2.0 - MARGIN,
+            LAUNCHER_POS.y - MARGIN * 2.0,
+        );
+        let symmetries = match params.symmetry {
+            Symmetry::None => vec![Affine2::IDENTITY],
+            Symmetry::Mirror => vec![Affine2::IDENTITY, Affine2::from_scale(Vec2::new(-1.0, 1.0))],
+            Symmetry::Radial(copies) => {
+                let (center, copies) = (area.center(), copies.max(1));
+                (0..copies)
+                    .map(|i| {
+                        let angle = i as f32 / copies as f32 * TAU;
+                        Affine2::from_translation(center)
+                            * Affine2::from_angle(angle)
+                            * Affine2::from_translation(-center)
+                    })
+                    .collect()
+            }
+        };
+        Self {
+            params,
+            rng,
+            area,
+            symmetries,
+            groups: Vec::new(),
+            routes: Vec::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    fn random_point(&mut self) -> Vec2 {
+        Vec2::new(
+            self.range(self.area.min.x, self.area.max.x),
+            self.range(self.area.min.y, self.area.max.y),
+        )
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + self.rng.f32() * (max - min)
+    }
+
+    fn peg_total(&self) -> usize {
+        self.groups.iter().map(|g| g.pegs.len()).sum()
+    }
+
+    fn is_free(&self, pos: Vec2) -> bool {
+        self.area.contains(pos)
+            && self
+                .groups
+                .iter()
+                .flat_map(|g| g.pegs.iter())
+                .all(|(p, _)| p.distance(pos) >= PEG_SPACING)
+            && self
+                .routes
+                .iter()
+                .all(|p| p.distance(pos) >= ROUTE_CLEARANCE)
+    }
+
+    /// Places the peg with all of its symmetric copies, if there is room for them.
+    fn place(&mut self, pos: Vec2, rotation: f32, peg_types: (&'static str, &'static str)) -> bool {
+        let pegs: Vec<_> = self
+            .symmetries
+            .iter()
+            .map(|s| {
+                let direction = s.transform_vector2(Vec2::from_angle(rotation));
+                (s.transform_point2(pos), direction.y.atan2(direction.x))
+            })
+            .collect();
+        let copies_apart = pegs.iter().enumerate().all(|(i, (a, _))| {
+            pegs[i + 1..]
+                .iter()
+                .all(|(b, _)| a.distance(*b) >= PEG_SPACING)
+        });
+        if !copies_apart || !pegs.iter().all(|(p, _)| self.is_free(*p)) {
+            return false;
+        }
+        self.groups.push(PegGroup { pegs, peg_types });
+        true
+    }
+
+    fn random_peg_types(&mut self) -> (&'static str, &'static str) {
+        GRID_PEG_TYPES[self.rng.usize(..GRID_PEG_TYPES.len())]
+    }
+
+    /// Loop of pegs moving around a smooth circle, along with its symmetric copies.
+    fn add_moving_group(&mut self) {
+        for _ in 0..PLACEMENT_TRIES {
+            let center = self.random_point();
+            let radius = self.range(PEG_SPACING * 1.5, PEG_SPACING * 3.0);
+            let point_count = 4 + self.rng.usize(..3);
+            let circle: Vec<_> = (0..point_count)
+                .map(|i| center + Vec2::from_angle(i as f32 / point_count as f32 * TAU) * radius)
+                .collect();
+            let paths: Vec<PathDef> = self
+                .symmetries
+                .iter()
+                .map(|s| PathDef {
+                    mode: PathMode::Loop,
+                    move_speed: 100.0,
+                    points: circle
+                        .iter()
+                        .map(|&p| PathPointDef::new(s.transform_point2(p)))
+                        .collect(),
+                    agents: Vec::new(),
+                    tolerance: None,
+                })
+                .collect();
+            let curve = paths[0].curve();
+            let length = curve.length();
+            let samples = (length / PEG_RADIUS).ceil() as usize;
+            let routes: Vec<Vec<Vec2>> = paths
+                .iter()
+                .map(|path| {
+                    let curve = path.curve();
+                    (0..samples)
+                        .map(|i| curve.get_pos_at_length(i as f32 / samples as f32 * length))
+                        .collect()
+                })
+                .collect();
+            let routes_apart = routes.iter().enumerate().all(|(i, a)| {
+                routes[i + 1..]
+                    .iter()
+                    .flatten()
+                    .all(|q| a.iter().all(|p| p.distance(*q) >= ROUTE_CLEARANCE))
+            });
+            if !routes_apart || !routes.iter().flatten().all(|p| self.is_free(*p)) {
+                continue;
+            }
+            let agent_count = 3 + self.rng.usize(..3);
+            let agents: Vec<_> = (0..agent_count)
+                .map(|i| PathAgentDef {
+                    t: i as f32 / agent_count as f32 * point_count as f32,
+                    peg_type: "rect".to_string(),
+                    orientation: Some(0.0),
+                })
+                .collect();
+            for mut path in paths {
+                path.agents = agents.clone();
+                self.paths.push(path);
+            }
+            self.routes.extend(routes.into_iter().flatten());
+            return;
+        }
+    }
+
+    /// Tight group of up to `budget` pegs around a random point.
+    fn add_cluster(&mut self, budget: usize) {
+        let center = self.random_point();
+        let radius = self.range(PEG_SPACING * 1.5, PEG_SPACING * 3.0);
+        let peg_types = self.random_peg_types();
+        let target = self.peg_total() + budget;
+        for _ in 0..budget * PLACEMENT_TRIES {
+            if self.peg_total() >= target {
+                break;
+            }
+            let offset = Vec2::from_angle(self.range(0.0, TAU)) * radius * self.rng.f32().sqrt();
+            self.place(center + offset, 0.0, peg_types);
+        }
+    }
+
+    /// Up to `budget` rectangular pegs lined up along a circular arc.
+    fn add_arc(&mut self, budget: usize) {
+        let center = self.random_point();
+        let radius = self.range(PEG_SPACING * 2.0, PEG_SPACING * 5.0);
+        let start = self.range(0.0, TAU);
+        let step = PEG_SPACING * 1.2 / radius;
+        let target = self.peg_total() + budget;
+        let mut angle = 0.0;
+        while angle < PI * 1.5 && self.peg_total() < target {
+            let direction = Vec2::from_angle(start + angle);
+            self.place(
+                center + direction * radius,
+                start + angle + PI / 2.0,
+                ("rect", "rect_target"),
+            );
+            angle += step;
+        }
+    }
+
+    fn build(mut self) -> Level {
+        let params = self.params;
+        for _ in 0..params.moving_groups {
+            self.add_moving_group();
+        }
+        // Clusters and arcs get an equal share of pegs, scattered ones fill up the rest
+        let budget = params.peg_count / (params.clusters + params.arcs + 1);
+        for _ in 0..params.clusters {
+            self.add_cluster(budget);
+        }
+        for _ in 0..params.arcs {
+            self.add_arc(budget);
+        }
+        for _ in 0..params.peg_count * PLACEMENT_TRIES {
+            if self.peg_total() >= params.peg_count {
+                break;
+            }
+            let pos = self.random_point();
+            let peg_types = self.random_peg_types();
+            self.place(pos, 0.0, peg_types);
+        }
+
+        // Whole groups become targets, so copies keep looking the same
+        let target_count =
+            ((self.peg_total() as f32 * params.target_ratio).round() as usize).max(1);
+        let mut order: Vec<_> = (0..self.groups.len()).collect();
+        self.rng.shuffle(&mut order);
+        let mut is_target = vec![false; self.groups.len()];
+        let mut targets = 0;
+        for i in order {
+            let size = self.groups[i].pegs.len();
+            if targets == 0 || targets + size <= target_count {
+                is_target[i] = true;
+                targets += size;
+            }
+        }
+
+        let pegs = self
+            .groups
+            .iter()
+            .zip(is_target)
+            .flat_map(|(group, is_target)| {
+                let peg_type = if is_target {
+                    group.peg_types.1
+                } else {
+                    group.peg_types.0
+                };
+                group.pegs.iter().map(move |(pos, rotation)| PegDef {
+                    position: (*pos).into(),
+                    rotation: rotation.to_degrees(),
+                    peg_type: peg_type.to_string(),
+                })
+            })
+            .collect();
+        Level {
+            walls: arena_walls(),
+            pegs,
+            paths: self.paths,
+            ..Default::default()
+        }
+    }
+}
+
+/// Generates levels until one has every target reachable from the launcher.
+pub fn generate(
+    params: &GeneratorParams,
+    shapes: &HashMap<String, PegTypeShape>,
+) -> Result<Level, String> {
+    let mut rng = fastrand::Rng::with_seed(params.seed);
+    for _ in 0..params.attempts.max(1) {
+        let level = LevelBuilder::new(params, rng.fork()).build();
+        let pegs = level_pegs(&level, shapes);
+        if unreachable_targets(&level, &pegs).is_empty() {
+            return Ok(level);
+        }
+    }
+    Err(format!(
+        "None of {} generated levels had every target reachable",
+        params.attempts.max(1)
+    ))
+}
+
+/// Runs `reggle generate <params> <output>`, where params are [`GeneratorParams`] in RON,
+/// either inline or in a `.ron` file. Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let [params, output] = args else {
+        eprintln!("Usage: reggle generate <params> <output.level.ron>");
+        eprintln!(
+            "Example: reggle generate \"(seed: 7, symmetry: Radial(5))\" generated.level.ron"
+        );
+        return 2;
+    };
+    let params = if params.ends_with(".ron") {
+        match std::fs::read_to_string(params) {
+            Ok(params) => params,
+            Err(e) => {
+                eprintln!("{params}: {e}");
+                return 1;
+            }
+        }
+    } else {
+        params.clone()
+    };
+    let params: GeneratorParams = match ron::from_str(&params) {
+        Ok(params) => params,
+        Err(e) => {
+            eprintln!("Invalid generator parameters: {e}");
+            return 2;
+        }
+    };
+    let result = read_peg_shapes(std::path::Path::new(PEG_TYPES_PATH))
+        .and_then(|shapes| generate(&params, &shapes))
+        .and_then(|level| {
+            let data = level.to_ron().map_err(|e| e.to_string())?;
+            std::fs::write(output, data).map_err(|e| format!("{output}: {e}"))?;
+            Ok(level)
+        });
+    match result {
+        Ok(level) => {
+            let agents: usize = level.paths.iter().map(|p| p.agents.len()).sum();
+            println!(
+                "Wrote {output} with {} pegs and {agents} moving pegs",
+                level.pegs.len()
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
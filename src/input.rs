@@ -33,6 +33,11 @@ impl GameInput {
 pub enum GameAction {
     Shoot,
     MoveLauncher,
+    ToggleMute,
+    NextTheme,
+    ToggleEditor,
+    Back,
+    Confirm,
 }
 
 fn input_state_system(
@@ -57,6 +62,28 @@ fn input_state_system(
         input_state.lock_input = !input_state.lock_input
     }
 
+    if keys.just_pressed(KeyCode::M) {
+        input_state
+            .just_active_actions
+            .insert(GameAction::ToggleMute);
+    }
+    if keys.just_pressed(KeyCode::T) {
+        input_state
+            .just_active_actions
+            .insert(GameAction::NextTheme);
+    }
+    if keys.just_pressed(KeyCode::F1) {
+        input_state
+            .just_active_actions
+            .insert(GameAction::ToggleEditor);
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        input_state.just_active_actions.insert(GameAction::Back);
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        input_state.just_active_actions.insert(GameAction::Confirm);
+    }
+
     if input_state.lock_input {
         return;
     }
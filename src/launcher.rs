@@ -1,23 +1,24 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::{RapierConfiguration, Velocity};
 
-use crate::common::{GameState, InGameState};
-use crate::LAUNCHER_BASE_POWER;
+use crate::common::{GameState, GameStats, InGameState};
 use crate::{
     assets::GameAssets,
     ball::BallBundle,
     input::{GameAction, GameInput},
 };
+use crate::{LAUNCHER_BASE_POWER, LAUNCHER_POS};
 
 pub struct LauncherPlugin;
 
 impl Plugin for LauncherPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(GameState::InGame), setup_ball_launcher)
+            .add_systems(OnExit(GameState::InGame), despawn_launcher)
             .add_systems(
                 Update,
                 (
-                    launcher_control_system,
+                    launcher_control_system.run_if(not(in_state(InGameState::Editor))),
                     ball_launcher_system.run_if(in_state(InGameState::Launcher)),
                 )
                     .run_if(in_state(GameState::InGame)),
@@ -44,7 +45,7 @@ fn setup_ball_launcher(mut commands: Commands, game_assets: Res<GameAssets>) {
                 ..Default::default()
             },
             texture: game_assets.launcher.image.clone(),
-            transform: Transform::from_xyz(0.0, 150.0, 1.0),
+            transform: Transform::from_translation(LAUNCHER_POS.extend(1.0)),
             ..Default::default()
         },
         Name::new("Launcher"),
@@ -55,6 +56,12 @@ fn setup_ball_launcher(mut commands: Commands, game_assets: Res<GameAssets>) {
     ));
 }
 
+fn despawn_launcher(mut commands: Commands, launcher: Query<Entity, With<Launcher>>) {
+    for e in launcher.iter() {
+        commands.entity(e).despawn();
+    }
+}
+
 fn angle_to_hit_target(start_pos: Vec2, target_pos: Vec2, g: f32, v: f32) -> f32 {
     let target = target_pos - start_pos;
     let (x, y) = (target.x, target.y);
@@ -93,9 +100,14 @@ fn ball_launcher_system(
     mut commands: Commands,
     input_state: Res<GameInput>,
     game_assets: Res<GameAssets>,
+    mut game_stats: ResMut<GameStats>,
     launcher: Query<(&Transform, &Launcher)>,
 ) {
-    if input_state.just_active(GameAction::Shoot) {
+    if input_state.just_active(GameAction::Shoot) && game_stats.balls_left != Some(0) {
+        if let Some(balls_left) = &mut game_stats.balls_left {
+            *balls_left -= 1;
+        }
+        game_stats.balls_used += 1;
         commands.insert_resource(NextState(Some(InGameState::Ball)));
         let (tr, launcher) = launcher.single();
         commands
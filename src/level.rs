@@ -0,0 +1,271 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use bevy_rapier2d::prelude::Collider;
+use serde::{Deserialize, Serialize};
+
+use crate::assets::GameAssets;
+use crate::brick::{spawn_brick_run, BrickRunDef};
+use crate::common::{GameState, GameStats, Wall};
+use crate::elements::{spawn_element, ElementDef, LevelElement};
+use crate::path::{spawn_path, Path, PathDef};
+use crate::peg::{grid_pegs, rebuild_peg_types, Peg, PegDef};
+use crate::peg_type::PegTypeRegistry;
+use crate::rotator::{spawn_rotator, Rotator, RotatorDef};
+use crate::trajectory::TrajectoryWorld;
+use crate::{ARENA_POS, ARENA_SIZE};
+
+const WALL_HALF_THICKNESS: f32 = 10.0;
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .add_systems(
+                OnEnter(GameState::InGame),
+                spawn_level.after(rebuild_peg_types),
+            )
+            .add_systems(Update, reload_level.run_if(in_state(GameState::InGame)))
+            .add_systems(OnExit(GameState::InGame), despawn_level);
+    }
+}
+
+/// Level layout loaded from a `*.level.ron` file.
+#[derive(Serialize, Deserialize, TypeUuid, TypePath, Default, Clone)]
+#[uuid = "5b1e9d37-2c4a-4f86-8e0d-7a3f6b2c1d48"]
+pub struct Level {
+    /// Arena walls are used when left out.
+    #[serde(default = "arena_walls")]
+    pub walls: Vec<WallDef>,
+    /// Random grid of pegs is spawned when left out.
+    #[serde(default)]
+    pub pegs: Vec<PegDef>,
+    #[serde(default)]
+    pub paths: Vec<PathDef>,
+    #[serde(default)]
+    pub elements: Vec<ElementDef>,
+    #[serde(default)]
+    pub bricks: Vec<BrickRunDef>,
+    #[serde(default)]
+    pub rotators: Vec<RotatorDef>,
+    /// Track that loops while the level is played, as a path in the assets folder.
+    #[serde(default)]
+    pub music: Option<String>,
+}
+
+impl Level {
+    /// Level as it is written in level files.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        let pretty = ron::ser::PrettyConfig::default().struct_names(false);
+        ron::ser::to_string_pretty(self, pretty)
+    }
+
+    /// Writes the level to `path` in the assets folder.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &std::path::Path) {
+        let data = match self.to_ron() {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to serialize level: {e}");
+                return;
+            }
+        };
+        let file_path = std::path::Path::new("assets").join(path);
+        match std::fs::write(&file_path, data) {
+            Ok(()) => info!("Saved level to {}", file_path.display()),
+            Err(e) => error!("Failed to write {}: {e}", file_path.display()),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self, _path: &std::path::Path) {
+        warn!("Levels can't be saved in the browser");
+    }
+}
+
+/// Static wall, as it is written in level files.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WallDef {
+    pub position: (f32, f32),
+    pub half_size: (f32, f32),
+    /// Counterclockwise, in degrees.
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+impl WallDef {
+    pub fn transform(&self) -> Transform {
+        Transform::from_translation(Vec2::from(self.position).extend(0.0))
+            .with_rotation(Quat::from_rotation_z(self.rotation.to_radians()))
+    }
+}
+
+pub fn arena_walls() -> Vec<WallDef> {
+    let wall = |offset: Vec2, half_size: Vec2| WallDef {
+        position: (ARENA_POS + offset).into(),
+        half_size: half_size.into(),
+        rotation: 0.0,
+    };
+    vec![
+        wall(
+            Vec2::new(0.0, ARENA_SIZE.y / 2.0),
+            Vec2::new(ARENA_SIZE.x / 2.0, WALL_HALF_THICKNESS),
+        ),
+        wall(
+            Vec2::new(ARENA_SIZE.x / 2.0, 0.0),
+            Vec2::new(WALL_HALF_THICKNESS, ARENA_SIZE.y / 2.0),
+        ),
+        wall(
+            Vec2::new(-ARENA_SIZE.x / 2.0, 0.0),
+            Vec2::new(WALL_HALF_THICKNESS, ARENA_SIZE.y / 2.0),
+        ),
+    ]
+}
+
+pub fn spawn_wall(commands: &mut Commands, def: &WallDef, color: Color) -> Entity {
+    let half_size = Vec2::from(def.half_size);
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(half_size * 2.0),
+                color,
+                ..Default::default()
+            },
+            transform: def.transform(),
+            ..Default::default()
+        })
+        .insert(Wall)
+        .insert(Collider::cuboid(half_size.x, half_size.y))
+        .insert(Name::new("Wall"))
+        .id()
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level: Level = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+/// Spawns everything the level is made of and returns how many target pegs it has.
+fn spawn_level_entities(
+    commands: &mut Commands,
+    level: &Level,
+    registry: &PegTypeRegistry,
+    wall_color: Color,
+) -> usize {
+    let is_target = |peg_type: &str| registry.get(peg_type).map_or(false, |t| t.is_target);
+    let mut targets = 0;
+    for wall in level.walls.iter() {
+        spawn_wall(commands, wall, wall_color);
+    }
+    let pegs = if level.pegs.is_empty() {
+        grid_pegs()
+    } else {
+        level.pegs.iter().map(|p| p.bundle()).collect()
+    };
+    targets += pegs.iter().filter(|p| is_target(&p.peg.peg_type)).count();
+    commands.spawn_batch(pegs);
+    for path in level.paths.iter() {
+        spawn_path(commands, path);
+        targets += path
+            .agents
+            .iter()
+            .filter(|a| is_target(&a.peg_type))
+            .count();
+    }
+    for element in level.elements.iter() {
+        spawn_element(commands, element);
+    }
+    for run in level.bricks.iter() {
+        let spawned = spawn_brick_run(commands, registry, run);
+        if is_target(&run.peg_type) {
+            targets += spawned;
+        }
+    }
+    for rotator in level.rotators.iter() {
+        spawn_rotator(commands, rotator);
+        targets += rotator
+            .pegs
+            .iter()
+            .filter(|p| is_target(&p.peg_type))
+            .count();
+    }
+    targets
+}
+
+pub fn spawn_level(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    levels: Res<Assets<Level>>,
+    registry: Res<PegTypeRegistry>,
+    mut game_stats: ResMut<GameStats>,
+) {
+    let Some(level) = levels.get(&game_assets.level) else {return};
+    game_stats.target_pegs_left =
+        spawn_level_entities(&mut commands, level, &registry, game_assets.wall_color);
+}
+
+/// Top-level entities the level is made of.
+type LevelRoot = (
+    Or<(
+        With<Wall>,
+        With<Peg>,
+        With<Path>,
+        With<Rotator>,
+        With<LevelElement>,
+    )>,
+    Without<Parent>,
+);
+
+fn despawn_level(mut commands: Commands, level_entities: Query<Entity, LevelRoot>) {
+    for entity in level_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Respawns the level when its file changes on disk. Score and launcher are kept as they are.
+fn reload_level(
+    mut commands: Commands,
+    mut level_events: EventReader<AssetEvent<Level>>,
+    game_assets: Res<GameAssets>,
+    levels: Res<Assets<Level>>,
+    registry: Res<PegTypeRegistry>,
+    mut game_stats: ResMut<GameStats>,
+    mut trajectory_world: ResMut<TrajectoryWorld>,
+    level_entities: Query<Entity, LevelRoot>,
+) {
+    let modified = level_events.iter().any(|e| match e {
+        AssetEvent::Modified { handle } => *handle == game_assets.level,
+        _ => false,
+    });
+    if !modified {
+        return;
+    }
+    let Some(level) = levels.get(&game_assets.level) else {return};
+    info!("Level changed on disk, respawning it");
+    for entity in level_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    // Everything mirrored in it is respawned, so it is simpler to start over
+    trajectory_world.clear();
+    game_stats.target_pegs_left =
+        spawn_level_entities(&mut commands, level, &registry, game_assets.wall_color);
+}
@@ -0,0 +1,385 @@
+use std::collections::BTreeSet;
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy_rapier2d::na;
+use bevy_rapier2d::parry::query::intersection_test;
+use bevy_rapier2d::prelude::{CoefficientCombineRule, Collider, Restitution};
+use reggle_path::{Path, PathMode};
+
+use crate::brick::brick_wedges_along_path;
+use crate::elements::ElementDef;
+use crate::level::Level;
+use crate::path::PathDef;
+use crate::peg::grid_pegs;
+use crate::peg_type::{read_peg_shapes, PegShape, PegTypeShape};
+use crate::trajectory::TrajectoryWorld;
+use crate::{ARENA_POS, ARENA_SIZE, LAUNCHER_BASE_POWER, LAUNCHER_POS, PEG_RADIUS};
+
+/// Peg types levels are checked with.
+pub const PEG_TYPES_PATH: &str = "assets/pegs/default.pegs.ron";
+
+/// Distance between points a path agent is checked at along its route.
+const ROUTE_STEP: f32 = PEG_RADIUS / 2.0;
+/// Launch directions tried, spread evenly around the launcher.
+const SHOT_DIRECTIONS: usize = 240;
+/// Collisions followed in every shot, so pegs reached after a bounce count too.
+const SHOT_COLLISIONS: usize = 3;
+
+/// Peg of a level, wherever in the level file it comes from.
+pub struct LevelPeg {
+    pub peg_type: String,
+    pub collider: Collider,
+    pub transform: Transform,
+    pub is_target: bool,
+    pub motion: PegMotion,
+}
+
+/// What moves a peg. Moving pegs are placed where they start.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PegMotion {
+    Static,
+    /// Agent of the path with this index in the level.
+    Path(usize),
+    /// Peg of the rotator with this index in the level.
+    Rotator(usize),
+}
+
+impl LevelPeg {
+    fn new(shape: &PegTypeShape, peg_type: &str, transform: Transform, motion: PegMotion) -> Self {
+        Self {
+            peg_type: peg_type.to_string(),
+            collider: shape.shape.collider(),
+            transform,
+            is_target: shape.is_target,
+            motion,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let pos = self.transform.translation;
+        let place = match self.motion {
+            PegMotion::Static => String::new(),
+            PegMotion::Path(i) => format!(" on path {i}"),
+            PegMotion::Rotator(i) => format!(" on rotator {i}"),
+        };
+        format!(
+            "\"{}\" peg at ({:.0}, {:.0}){place}",
+            self.peg_type, pos.x, pos.y
+        )
+    }
+}
+
+/// Every peg the level spawns. Pegs of unknown types are left out.
+pub fn level_pegs(level: &Level, shapes: &HashMap<String, PegTypeShape>) -> Vec<LevelPeg> {
+    let mut pegs = Vec::new();
+    let mut add_peg = |peg_type: &str, transform: Transform, motion: PegMotion| {
+        if let Some(shape) = shapes.get(peg_type) {
+            pegs.push(LevelPeg::new(shape, peg_type, transform, motion));
+        }
+    };
+    if level.pegs.is_empty() {
+        for peg in grid_pegs() {
+            add_peg(
+                &peg.peg.peg_type,
+                peg.sprite_bundle.transform,
+                PegMotion::Static,
+            );
+        }
+    }
+    for peg in level.pegs.iter() {
+        let transform = Transform::from_translation(Vec2::from(peg.position).extend(0.0))
+            .with_rotation(Quat::from_rotation_z(peg.rotation.to_radians()));
+        add_peg(&peg.peg_type, transform, PegMotion::Static);
+    }
+    for (i, path) in level.paths.iter().enumerate() {
+        let curve = path.curve();
+        if !curve.is_valid() {
+            continue;
+        }
+        for agent in path.agents.iter() {
+            let (position, velocity) = curve.get_world_pos_and_vel(agent.t);
+            let angle = agent.orientation.map_or(0.0, |offset| {
+                velocity.y.atan2(velocity.x) + offset.to_radians()
+            });
+            let transform = Transform::from_translation(position.extend(0.0))
+                .with_rotation(Quat::from_rotation_z(angle));
+            add_peg(&agent.peg_type, transform, PegMotion::Path(i));
+        }
+    }
+    for (i, rotator) in level.rotators.iter().enumerate() {
+        let rotation = Quat::from_rotation_z(rotator.motion.angle(0.0));
+        let pivot = Vec2::from(rotator.pivot).extend(0.0);
+        for peg in rotator.pegs.iter() {
+            let offset = Vec2::from(peg.offset).extend(0.0);
+            let transform =
+                Transform::from_translation(pivot + rotation * offset).with_rotation(rotation);
+            add_peg(&peg.peg_type, transform, PegMotion::Rotator(i));
+        }
+    }
+    for run in level.bricks.iter() {
+        let Some(shape) = shapes.get(&run.peg_type) else {continue};
+        let PegShape::Brick { thickness } = shape.shape else {continue};
+        let points: Vec<_> = run.points.iter().map(|&p| Vec2::from(p)).collect();
+        let mode = if run.looped {
+            PathMode::Loop
+        } else {
+            PathMode::OnceStop
+        };
+        let path = Path::from_positions(&points, run.segment_type, mode);
+        if path.segment_count() == 0 {
+            continue;
+        }
+        for wedge in brick_wedges_along_path(&path, run.count, thickness, run.gap) {
+            let Some(collider) = Collider::convex_polygon(&wedge.corners) else {continue};
+            let transform = Transform::from_translation(wedge.center.extend(0.0))
+                .with_rotation(Quat::from_rotation_z(wedge.angle));
+            pegs.push(LevelPeg {
+                collider,
+                ..LevelPeg::new(shape, &run.peg_type, transform, PegMotion::Static)
+            });
+        }
+    }
+    pegs
+}
+
+/// Indices of target pegs in `pegs` that no launcher direction reaches, with the launcher
+/// where the game puts it. Bumper boosts, portals and gravity wells aren't simulated.
+pub fn unreachable_targets(level: &Level, pegs: &[LevelPeg]) -> Vec<usize> {
+    let mut world = TrajectoryWorld::headless();
+    let mut next_entity = 0;
+    let mut add_collider =
+        |collider: &Collider, transform: Transform, restitution: Option<&Restitution>| {
+            let entity = Entity::from_raw(next_entity);
+            next_entity += 1;
+            world.add_collider(entity, collider, &transform.into(), restitution, false);
+        };
+    // Pegs go first, so their entity index is their index in `pegs`
+    for peg in pegs.iter() {
+        add_collider(&peg.collider, peg.transform, None);
+    }
+    for wall in level.walls.iter() {
+        let collider = Collider::cuboid(wall.half_size.0, wall.half_size.1);
+        add_collider(&collider, wall.transform(), None);
+    }
+    for element in level.elements.iter() {
+        let ElementDef::Bumper {
+            position,
+            radius,
+            restitution,
+            ..
+        } = *element
+        else {continue};
+        let restitution = Restitution {
+            coefficient: restitution,
+            combine_rule: CoefficientCombineRule::Max,
+        };
+        let transform = Transform::from_translation(Vec2::from(position).extend(0.0));
+        add_collider(&Collider::ball(radius), transform, Some(&restitution));
+    }
+
+    let mut unreached: HashSet<usize> = (0..pegs.len()).filter(|&i| pegs[i].is_target).collect();
+    for i in 0..SHOT_DIRECTIONS {
+        if unreached.is_empty() {
+            break;
+        }
+        let direction = Vec2::from_angle(i as f32 / SHOT_DIRECTIONS as f32 * TAU);
+        let linvel = direction * LAUNCHER_BASE_POWER;
+        for entity in world.shot_collisions(LAUNCHER_POS, linvel, SHOT_COLLISIONS) {
+            unreached.remove(&(entity.index() as usize));
+        }
+    }
+    let mut unreached: Vec<_> = unreached.into_iter().collect();
+    unreached.sort_unstable();
+    unreached
+}
+
+fn isometry(transform: &Transform) -> na::Isometry2<f32> {
+    let (angle, _, _) = transform.rotation.to_euler(EulerRot::ZYX);
+    na::Isometry2::new(transform.translation.truncate().into(), angle)
+}
+
+fn intersects(a: &Collider, a_tr: &Transform, b: &Collider, b_tr: &Transform) -> bool {
+    intersection_test(&isometry(a_tr), &*a.raw, &isometry(b_tr), &*b.raw).unwrap_or(false)
+}
+
+/// Peg types the level uses that aren't in `shapes`.
+fn unknown_peg_types(level: &Level, shapes: &HashMap<String, PegTypeShape>) -> BTreeSet<String> {
+    let used = level
+        .pegs
+        .iter()
+        .map(|p| &p.peg_type)
+        .chain(
+            level
+                .paths
+                .iter()
+                .flat_map(|p| p.agents.iter().map(|a| &a.peg_type)),
+        )
+        .chain(
+            level
+                .rotators
+                .iter()
+                .flat_map(|r| r.pegs.iter().map(|p| &p.peg_type)),
+        )
+        .chain(level.bricks.iter().map(|b| &b.peg_type));
+    used.filter(|t| !shapes.contains_key(*t)).cloned().collect()
+}
+
+/// Static pegs hit by agents moving along the path, as indices into `pegs`.
+fn route_collisions(
+    path: &PathDef,
+    shapes: &HashMap<String, PegTypeShape>,
+    pegs: &[LevelPeg],
+) -> HashSet<usize> {
+    let curve = path.curve();
+    let length = curve.length();
+    let mut hits = HashSet::new();
+    if length <= 0.0 {
+        return hits;
+    }
+    let steps = (length / ROUTE_STEP).ceil() as usize;
+    let mut checked = HashSet::new();
+    for agent in path.agents.iter() {
+        let Some(shape) = shapes.get(&agent.peg_type) else {continue};
+        // Agents of the same type and orientation sweep the same area
+        let orientation = agent.orientation.map(f32::to_bits);
+        if !checked.insert((&agent.peg_type, orientation)) {
+            continue;
+        }
+        let collider = shape.shape.collider();
+        for step in 0..=steps {
+            let t = curve.t_at_length(step as f32 / steps as f32 * length);
+            let (position, velocity) = curve.get_world_pos_and_vel(t);
+            let mut transform = Transform::from_translation(position.extend(0.0));
+            if let Some(offset) = agent.orientation {
+                let angle = velocity.y.atan2(velocity.x) + offset.to_radians();
+                transform.rotation = Quat::from_rotation_z(angle);
+            }
+            for (i, peg) in pegs.iter().enumerate() {
+                if peg.motion == PegMotion::Static
+                    && intersects(&collider, &transform, &peg.collider, &peg.transform)
+                {
+                    hits.insert(i);
+                }
+            }
+        }
+    }
+    hits
+}
+
+/// Problems that would make the level play oddly, one message each.
+pub fn check_level(level: &Level, shapes: &HashMap<String, PegTypeShape>) -> Vec<String> {
+    let mut problems = Vec::new();
+    for peg_type in unknown_peg_types(level, shapes) {
+        problems.push(format!("Unknown peg type \"{peg_type}\""));
+    }
+    let pegs = level_pegs(level, shapes);
+
+    // Route checks below cover path agents
+    let placed: Vec<_> = (0..pegs.len())
+        .filter(|&i| !matches!(pegs[i].motion, PegMotion::Path(_)))
+        .collect();
+    for (n, &i) in placed.iter().enumerate() {
+        for &j in placed[n + 1..].iter() {
+            let (a, b) = (&pegs[i], &pegs[j]);
+            if intersects(&a.collider, &a.transform, &b.collider, &b.transform) {
+                problems.push(format!("{} overlaps {}", a.describe(), b.describe()));
+            }
+        }
+    }
+
+    let arena_min = ARENA_POS - ARENA_SIZE / 2.0;
+    let arena_max = ARENA_POS + ARENA_SIZE / 2.0;
+    let walls: Vec<_> = level
+        .walls
+        .iter()
+        .map(|w| {
+            (
+                Collider::cuboid(w.half_size.0, w.half_size.1),
+                w.transform(),
+            )
+        })
+        .collect();
+    for peg in pegs.iter() {
+        let aabb = peg.collider.raw.compute_aabb(&isometry(&peg.transform));
+        let (min, max) = (
+            Vec2::new(aabb.mins.x, aabb.mins.y),
+            Vec2::new(aabb.maxs.x, aabb.maxs.y),
+        );
+        if min.cmplt(arena_min).any() || max.cmpgt(arena_max).any() {
+            problems.push(format!("{} is outside of the arena", peg.describe()));
+        }
+        for (i, (collider, transform)) in walls.iter().enumerate() {
+            if intersects(&peg.collider, &peg.transform, collider, transform) {
+                problems.push(format!("{} intersects wall {i}", peg.describe()));
+            }
+        }
+    }
+
+    for (i, path) in level.paths.iter().enumerate() {
+        if path.points.len() < 2 {
+            problems.push(format!("Path {i} has fewer than two points"));
+            continue;
+        }
+        let mut hits: Vec<_> = route_collisions(path, shapes, &pegs).into_iter().collect();
+        hits.sort_unstable();
+        for peg in hits {
+            let peg = pegs[peg].describe();
+            problems.push(format!("Agents of path {i} run into {peg}"));
+        }
+    }
+
+    for (i, rotator) in level.rotators.iter().enumerate() {
+        let period = rotator.motion.period();
+        if period.is_some_and(|period| period.is_nan() || period <= 0.0) {
+            problems.push(format!(
+                "Rotator {i} swings with a period that isn't positive"
+            ));
+        }
+    }
+
+    if !pegs.iter().any(|p| p.is_target) {
+        problems.push("Level has no target pegs".to_string());
+    } else {
+        for i in unreachable_targets(level, &pegs) {
+            let peg = pegs[i].describe();
+            problems.push(format!("{peg} can't be reached from the launcher"));
+        }
+    }
+    problems
+}
+
+/// Runs `reggle validate <level files>`, printing problems found in every level.
+/// Returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    if args.is_empty() {
+        eprintln!("Usage: reggle validate <level.ron>...");
+        return 2;
+    }
+    let shapes = match read_peg_shapes(std::path::Path::new(PEG_TYPES_PATH)) {
+        Ok(shapes) => shapes,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+    let mut failed = false;
+    for path in args {
+        let problems = match std::fs::read(path) {
+            Ok(bytes) => match ron::de::from_bytes::<Level>(&bytes) {
+                Ok(level) => check_level(&level, &shapes),
+                Err(e) => vec![e.to_string()],
+            },
+            Err(e) => vec![e.to_string()],
+        };
+        if problems.is_empty() {
+            println!("{path}: ok");
+        }
+        for problem in problems.iter() {
+            println!("{path}: {problem}");
+        }
+        failed |= !problems.is_empty();
+    }
+    i32::from(failed)
+}
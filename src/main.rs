@@ -1,6 +1,9 @@
 #![allow(clippy::too_many_arguments)]
 #![allow(clippy::type_complexity)]
 
+use std::time::Duration;
+
+use bevy::asset::ChangeWatcher;
 use bevy::prelude::*;
 use bevy_kira_audio::AudioPlugin;
 use bevy_prototype_lyon::prelude::*;
@@ -9,28 +12,50 @@ use bevy_tweening::TweeningPlugin;
 
 const PLAYER_BALL_RADIUS: f32 = 10.0;
 const LAUNCHER_BASE_POWER: f32 = 500.0;
+const LAUNCHER_POS: Vec2 = Vec2::new(0.0, 150.0);
 const PEG_RADIUS: f32 = 13.0;
 const PIXELS_PER_METER: f32 = 100.0;
 const SCREEN_HEIGHT: f32 = 1000.0;
 const ARENA_SIZE: Vec2 = Vec2::new(1000.0, 800.0);
 const ARENA_POS: Vec2 = Vec2::new(0.0, -100.0);
+const GRAVITY: Vec2 = Vec2::new(0.0, -500.0);
 
 mod assets;
 mod ball;
+mod brick;
+mod campaign;
 mod common;
 mod debug;
+mod editor;
+mod elements;
+mod generator;
 mod input;
 mod launcher;
+mod level;
+mod level_check;
+mod menu;
 mod path;
 mod peg;
+mod peg_type;
+mod rotator;
+mod save;
+mod settings;
 mod sounds;
-mod spline;
+mod theme;
 mod trajectory;
 mod ui;
 
 use common::*;
 
 fn main() {
+    // Level tools run instead of the game when asked for
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("generate") => std::process::exit(generator::run(&args[1..])),
+        Some("validate") => std::process::exit(level_check::run(&args[1..])),
+        _ => (),
+    }
+
     let mut app = App::new();
 
     app.add_state::<GameState>()
@@ -39,12 +64,16 @@ fn main() {
         .insert_resource(GameStats::default())
         .insert_resource(ClearColor(Color::BLACK))
         .insert_resource(RapierConfiguration {
-            gravity: Vec2::new(0., -500.),
+            gravity: GRAVITY,
             ..Default::default()
         })
         .add_plugins((
             // Engine
-            DefaultPlugins,
+            DefaultPlugins.set(AssetPlugin {
+                // Level files and other assets are reloaded when they change on disk
+                watch_for_changes: ChangeWatcher::with_delay(Duration::from_millis(200)),
+                ..Default::default()
+            }),
             AudioPlugin,
             ShapePlugin,
             TweeningPlugin,
@@ -59,9 +88,19 @@ fn main() {
             trajectory::TrajectoryPlugin,
             ui::UiPlugin,
             path::PathPlugin,
+            save::SavePlugin,
             sounds::SoundsPlugin,
+            theme::ThemePlugin,
+            level::LevelPlugin,
+            elements::ElementsPlugin,
+            rotator::RotatorPlugin,
+            editor::EditorPlugin,
+            campaign::CampaignPlugin,
+            menu::MenuPlugin,
         ))
-        .add_systems(OnEnter(GameState::InGame), (setup_graphics, setup_level));
+        .add_systems(Startup, setup_camera)
+        .add_systems(OnEnter(GameState::InGame), setup_graphics)
+        .add_systems(OnExit(GameState::InGame), despawn_graphics);
 
     #[cfg(feature = "exit_timeout")]
     app.add_systems(Update, exit_timeout_system);
@@ -78,7 +117,7 @@ fn exit_timeout_system(time: Res<Time>, mut writer: EventWriter<bevy::app::AppEx
     }
 }
 
-fn setup_graphics(mut commands: Commands, game_assets: Res<assets::GameAssets>) {
+fn setup_camera(mut commands: Commands) {
     commands.spawn((
         Camera2dBundle {
             projection: OrthographicProjection {
@@ -92,45 +131,22 @@ fn setup_graphics(mut commands: Commands, game_assets: Res<assets::GameAssets>)
         },
         MainCamera,
     ));
-    commands.spawn(SpriteBundle {
-        texture: game_assets.background_image.clone(),
-        transform: Transform::from_xyz(0.0, 0.0, -100.0),
-        ..Default::default()
-    });
 }
 
-fn spawn_wall(commands: &mut Commands, position: Vec2, width: f32, height: f32) {
-    commands
-        .spawn(SpriteBundle {
-            sprite: Sprite {
-                custom_size: Some(Vec2::new(width * 2.0, height * 2.0)),
-                color: Color::GRAY,
-                ..Default::default()
-            },
+fn setup_graphics(mut commands: Commands, game_assets: Res<assets::GameAssets>) {
+    let Some(background_image) = &game_assets.background_image else {return};
+    commands.spawn((
+        SpriteBundle {
+            texture: background_image.clone(),
+            transform: Transform::from_xyz(0.0, 0.0, -100.0),
             ..Default::default()
-        })
-        .insert(Collider::cuboid(width, height))
-        .insert(Transform::from_xyz(position.x, position.y, 0.0))
-        .insert(GlobalTransform::default());
+        },
+        Background,
+    ));
 }
 
-fn setup_level(mut commands: Commands) {
-    spawn_wall(
-        &mut commands,
-        ARENA_POS + Vec2::new(0.0, ARENA_SIZE.y / 2.0),
-        ARENA_SIZE.x / 2.0,
-        10.0,
-    );
-    spawn_wall(
-        &mut commands,
-        ARENA_POS + Vec2::new(ARENA_SIZE.x / 2.0, 0.0),
-        10.0,
-        ARENA_SIZE.y / 2.0,
-    );
-    spawn_wall(
-        &mut commands,
-        ARENA_POS - Vec2::new(ARENA_SIZE.x / 2.0, 0.0),
-        10.0,
-        ARENA_SIZE.y / 2.0,
-    );
+fn despawn_graphics(mut commands: Commands, background: Query<Entity, With<Background>>) {
+    for e in background.iter() {
+        commands.entity(e).despawn();
+    }
 }
@@ -0,0 +1,364 @@
+use bevy::prelude::*;
+
+use crate::assets::GameAssets;
+use crate::campaign::{Campaign, CampaignLevel, CampaignProgress, LevelPack, LevelPacks};
+use crate::common::GameState;
+use crate::editor::OpenEditor;
+use crate::input::{GameAction, GameInput};
+use crate::level::Level;
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.2);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.25, 0.25, 0.35);
+const LOCKED_TEXT_COLOR: Color = Color::GRAY;
+const ROW_WIDTH: f32 = 700.0;
+const ROW_MARGIN: f32 = 4.0;
+const EDIT_BUTTON_WIDTH: f32 = 100.0;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MenuPage>()
+            .add_systems(OnEnter(GameState::Menu), open_menu)
+            .add_systems(OnExit(GameState::Menu), despawn_menu)
+            .add_systems(
+                Update,
+                (
+                    menu_input_system,
+                    menu_button_system,
+                    build_menu.run_if(resource_changed::<MenuPage>()),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Menu)),
+            );
+    }
+}
+
+/// Page of the menu being shown.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+enum MenuPage {
+    /// Every pack, with progress through it.
+    #[default]
+    Packs,
+    /// Levels of the pack at this index in [`LevelPacks`].
+    Pack(usize),
+}
+
+#[derive(Clone, Copy)]
+enum MenuAction {
+    OpenPack(usize),
+    Play(CampaignLevel),
+    /// Plays the default level without a ball limit.
+    FreePlay,
+    /// Opens the pack level, or the default level if unset, in the editor.
+    Edit(Option<CampaignLevel>),
+    Back,
+}
+
+#[derive(Component)]
+struct MenuButton(MenuAction);
+
+#[derive(Component)]
+struct MenuUi;
+
+/// Shows the page the player was last on, so they get back to the pack they were playing.
+fn open_menu(mut page: ResMut<MenuPage>) {
+    page.set_changed();
+}
+
+fn despawn_menu(mut commands: Commands, menu_ui: Query<Entity, With<MenuUi>>) {
+    for e in menu_ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+/// Level that is played by confirming on a pack page: the first unlocked one not cleared yet.
+fn next_level(pack: &LevelPack, id: &str, progress: &CampaignProgress) -> Option<usize> {
+    let pack_progress = progress.pack(id);
+    (0..pack.levels.len()).find(|&level| {
+        pack_progress.is_unlocked(pack, level)
+            && !pack_progress.level(&pack.levels[level].key).cleared
+    })
+}
+
+/// Starts `level`, as part of the campaign if `current` is set.
+fn play(
+    commands: &mut Commands,
+    campaign: &mut Campaign,
+    game_assets: &mut GameAssets,
+    current: Option<CampaignLevel>,
+    level: Handle<Level>,
+) {
+    campaign.current = current;
+    game_assets.level = level;
+    commands.insert_resource(NextState(Some(GameState::InGame)));
+}
+
+fn pack_level(
+    level: CampaignLevel,
+    packs: &LevelPacks,
+    pack_assets: &Assets<LevelPack>,
+) -> Option<Handle<Level>> {
+    let (_, handle) = packs.packs.get(level.pack)?;
+    let pack = pack_assets.get(handle)?;
+    Some(pack.levels.get(level.level)?.level.clone())
+}
+
+fn run_action(
+    action: MenuAction,
+    commands: &mut Commands,
+    page: &mut MenuPage,
+    campaign: &mut Campaign,
+    game_assets: &mut GameAssets,
+    packs: &LevelPacks,
+    pack_assets: &Assets<LevelPack>,
+) {
+    match action {
+        MenuAction::OpenPack(pack) => *page = MenuPage::Pack(pack),
+        MenuAction::Back => *page = MenuPage::Packs,
+        MenuAction::FreePlay => {
+            let level = game_assets.default_level.clone();
+            play(commands, campaign, game_assets, None, level);
+        }
+        MenuAction::Play(current) => {
+            let Some(level) = pack_level(current, packs, pack_assets) else {return};
+            play(commands, campaign, game_assets, Some(current), level);
+        }
+        MenuAction::Edit(current) => {
+            let level = match current {
+                Some(current) => pack_level(current, packs, pack_assets),
+                None => Some(game_assets.default_level.clone()),
+            };
+            let Some(level) = level else {return};
+            // Edited levels are played without a ball limit or saved progress
+            play(commands, campaign, game_assets, None, level);
+            commands.insert_resource(OpenEditor(true));
+        }
+    }
+}
+
+fn menu_input_system(
+    mut commands: Commands,
+    input_state: Res<GameInput>,
+    mut page: ResMut<MenuPage>,
+    mut campaign: ResMut<Campaign>,
+    mut game_assets: ResMut<GameAssets>,
+    packs: Res<LevelPacks>,
+    pack_assets: Res<Assets<LevelPack>>,
+    progress: Res<CampaignProgress>,
+) {
+    let MenuPage::Pack(pack_index) = *page else {return};
+    if input_state.just_active(GameAction::Back) {
+        *page = MenuPage::Packs;
+        return;
+    }
+    if !input_state.just_active(GameAction::Confirm) {
+        return;
+    }
+    let Some((id, handle)) = packs.packs.get(pack_index) else {return};
+    let Some(pack) = pack_assets.get(handle) else {return};
+    let Some(level) = next_level(pack, id, &progress) else {return};
+    let action = MenuAction::Play(CampaignLevel {
+        pack: pack_index,
+        level,
+    });
+    run_action(
+        action,
+        &mut commands,
+        &mut page,
+        &mut campaign,
+        &mut game_assets,
+        &packs,
+        &pack_assets,
+    );
+}
+
+fn menu_button_system(
+    mut commands: Commands,
+    mut buttons: Query<(&Interaction, &MenuButton, &mut BackgroundColor), Changed<Interaction>>,
+    mut page: ResMut<MenuPage>,
+    mut campaign: ResMut<Campaign>,
+    mut game_assets: ResMut<GameAssets>,
+    packs: Res<LevelPacks>,
+    pack_assets: Res<Assets<LevelPack>>,
+) {
+    for (interaction, button, mut color) in buttons.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => run_action(
+                button.0,
+                &mut commands,
+                &mut page,
+                &mut campaign,
+                &mut game_assets,
+                &packs,
+                &pack_assets,
+            ),
+            Interaction::Hovered => *color = HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+}
+
+/// Text of a button, or of a locked level if there is no action.
+fn spawn_row(
+    cb: &mut ChildBuilder,
+    text: String,
+    font: &Handle<Font>,
+    width: f32,
+    action: Option<MenuAction>,
+) {
+    let style = Style {
+        width: Val::Px(width),
+        padding: UiRect::all(Val::Px(10.0)),
+        margin: UiRect::all(Val::Px(ROW_MARGIN)),
+        ..default()
+    };
+    let text = TextBundle::from_section(
+        text,
+        TextStyle {
+            font: font.clone(),
+            font_size: 28.0,
+            color: if action.is_some() {
+                Color::WHITE
+            } else {
+                LOCKED_TEXT_COLOR
+            },
+        },
+    );
+    match action {
+        Some(action) => {
+            cb.spawn((
+                ButtonBundle {
+                    style,
+                    background_color: BUTTON_COLOR.into(),
+                    ..default()
+                },
+                MenuButton(action),
+            ))
+            .with_children(|cb| {
+                cb.spawn(text);
+            });
+        }
+        None => {
+            cb.spawn(NodeBundle { style, ..default() })
+                .with_children(|cb| {
+                    cb.spawn(text);
+                });
+        }
+    }
+}
+
+/// Row of a level, with a button that opens it in the editor next to it.
+fn spawn_level_row(
+    cb: &mut ChildBuilder,
+    text: String,
+    font: &Handle<Font>,
+    action: Option<MenuAction>,
+    edit: MenuAction,
+) {
+    cb.spawn(NodeBundle::default()).with_children(|cb| {
+        let width = ROW_WIDTH - EDIT_BUTTON_WIDTH - ROW_MARGIN * 2.0;
+        spawn_row(cb, text, font, width, action);
+        spawn_row(cb, "Edit".to_string(), font, EDIT_BUTTON_WIDTH, Some(edit));
+    });
+}
+
+fn build_menu(
+    mut commands: Commands,
+    page: Res<MenuPage>,
+    game_assets: Res<GameAssets>,
+    packs: Res<LevelPacks>,
+    pack_assets: Res<Assets<LevelPack>>,
+    progress: Res<CampaignProgress>,
+    menu_ui: Query<Entity, With<MenuUi>>,
+) {
+    for e in menu_ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+    let font = &game_assets.normal_font;
+    let pack = match *page {
+        MenuPage::Packs => None,
+        MenuPage::Pack(index) => packs
+            .packs
+            .get(index)
+            .and_then(|(id, h)| Some((index, id, pack_assets.get(h)?))),
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            MenuUi,
+        ))
+        .with_children(|cb| {
+            let title = pack.map_or("Level packs".to_string(), |(_, _, p)| {
+                format!("{} by {}", p.name, p.author)
+            });
+            cb.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 48.0,
+                    color: Color::WHITE,
+                },
+            ));
+
+            let Some((pack_index, id, pack)) = pack else {
+                for (index, (id, handle)) in packs.packs.iter().enumerate() {
+                    let Some(pack) = pack_assets.get(handle) else {continue};
+                    let pack_progress = progress.pack(id);
+                    let score: usize = pack_progress.levels.values().map(|l| l.best_score).sum();
+                    let text = format!(
+                        "{} by {}  -  {}/{} cleared, score {score}",
+                        pack.name,
+                        pack.author,
+                        pack_progress.cleared_count(),
+                        pack.levels.len(),
+                    );
+                    spawn_row(cb, text, font, ROW_WIDTH, Some(MenuAction::OpenPack(index)));
+                }
+                let text = format!("Free play  -  best {}", progress.free_play_best);
+                let edit = MenuAction::Edit(None);
+                spawn_level_row(cb, text, font, Some(MenuAction::FreePlay), edit);
+                return;
+            };
+
+            let pack_progress = progress.pack(id);
+            for (index, level) in pack.levels.iter().enumerate() {
+                let record = pack_progress.level(&level.key);
+                let mut text = format!(
+                    "{}. {} by {}  -  par {}, {} balls",
+                    index + 1,
+                    level.name,
+                    level.author,
+                    level.par_score,
+                    level.balls,
+                );
+                if record.best_score > 0 {
+                    text += &format!(", best {}", record.best_score);
+                }
+                if let Some(balls) = record.fewest_balls {
+                    text += &format!(", cleared with {balls} balls");
+                }
+                let current = CampaignLevel {
+                    pack: pack_index,
+                    level: index,
+                };
+                let action = pack_progress
+                    .is_unlocked(pack, index)
+                    .then_some(MenuAction::Play(current));
+                spawn_level_row(cb, text, font, action, MenuAction::Edit(Some(current)));
+            }
+            let back = Some(MenuAction::Back);
+            spawn_row(cb, "Back".to_string(), font, ROW_WIDTH, back);
+        });
+}
@@ -1,9 +1,14 @@
+use std::ops::{Deref, DerefMut};
+
 use bevy::prelude::*;
+use bevy_rapier2d::prelude::RigidBody;
+use reggle_path::{AgentStep, PathCursor, Segment, SegmentType, Waypoint};
+use serde::{Deserialize, Serialize};
+
+pub use reggle_path::{PathEasingFunction, PathMode, TessellationTolerance};
 
-use crate::{
-    common::GameState,
-    spline::{Segment, SegmentType},
-};
+use crate::common::{GameState, InGameState};
+use crate::peg::{Peg, PegBundle};
 
 pub struct PathPlugin;
 
@@ -19,171 +24,302 @@ impl Plugin for PathPlugin {
                 .chain()
                 .run_if(in_state(GameState::InGame)),
         )
+        .add_event::<PathPointReached>()
+        .add_event::<PathLoopCompleted>()
         .register_type::<Path>()
         .register_type::<PathAgent>()
         .register_type::<PathPoint>();
     }
 }
 
-#[derive(Reflect, Default, Clone, Copy)]
-pub enum PathEasingFunction {
-    None,
-    #[default]
-    Constant,
+/// Sent when a path agent reaches one of the path points.
+#[derive(Event)]
+pub struct PathPointReached {
+    pub agent: Entity,
+    pub path: Entity,
+    pub point: Entity,
 }
 
-struct RawPathPoint {
-    pos: Vec2,
-    segment: Segment,
-    _speed_multiplier: f32,
-    easing_function: PathEasingFunction,
-    entity: Entity,
+/// Sent when a path agent goes around a looped path, or comes back to the start of a
+/// ping-pong path.
+#[derive(Event)]
+pub struct PathLoopCompleted {
+    pub agent: Entity,
+    pub path: Entity,
 }
 
-#[derive(Component, Reflect, Default, Clone)]
+#[derive(Component, Reflect, Clone)]
 pub struct PathPoint {
     pub segment_type: SegmentType,
-    pub _speed_multiplier: f32,
+    /// Multiplies path move speed on the segment starting at this point.
+    pub speed_multiplier: f32,
     pub easing_function: PathEasingFunction,
+    /// Seconds agents wait after reaching this point.
+    pub pause: f32,
+}
+
+impl Default for PathPoint {
+    fn default() -> Self {
+        Self {
+            segment_type: Default::default(),
+            speed_multiplier: 1.0,
+            easing_function: Default::default(),
+            pause: 0.0,
+        }
+    }
 }
 
 impl PathPoint {
-    fn as_raw(&self, translation: Vec3, self_entity: Entity) -> RawPathPoint {
+    fn as_waypoint(&self, translation: Vec3) -> Waypoint {
         let Self {
             segment_type,
-            _speed_multiplier,
+            speed_multiplier,
             easing_function,
+            pause,
         } = *self;
-        RawPathPoint {
+        Waypoint {
             pos: translation.truncate(),
-            entity: self_entity,
             segment: Segment::new(segment_type),
-            _speed_multiplier,
+            speed_multiplier,
             easing_function,
+            pause,
         }
     }
 }
 
+/// Path built from its `PathPoint` children, moves its `PathAgent` children along.
 #[derive(Component, Default, Reflect)]
 pub struct Path {
+    pub curve: reggle_path::Path,
+    /// Entities of `curve.points`, in the same order.
     #[reflect(ignore)]
-    points: Vec<RawPathPoint>,
-    pub move_speed: f32,
-    pub looped: bool,
+    point_entities: Vec<Entity>,
 }
 
 impl Path {
-    pub fn new(move_speed: f32, looped: bool) -> Self {
+    pub fn new(move_speed: f32, mode: PathMode) -> Self {
         Self {
-            move_speed,
-            looped,
+            curve: reggle_path::Path::new(move_speed, mode),
             ..Default::default()
         }
     }
-    pub fn is_valid(&self) -> bool {
-        !self.points.is_empty()
+}
+
+impl Deref for Path {
+    type Target = reggle_path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.curve
+    }
+}
+
+impl DerefMut for Path {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.curve
     }
-    pub fn get_world_pos(&self, t: f32) -> Vec2 {
-        let point_idx = (t.floor() as usize).clamp(0, self.points.len() - 1);
-        let point = &self.points[point_idx];
+}
 
-        if let PathEasingFunction::Constant = point.easing_function {
-            point
-                .segment
-                .get_pos_cached(t - point_idx as f32)
-                .unwrap_or(self.points[point_idx].pos)
-        } else {
-            let Some(neigbors) = self.get_neigbors_positions(point_idx) else {return point.pos};
-            point.segment.get_pos(t - point_idx as f32, &neigbors)
+#[derive(Component, Reflect, Default)]
+pub struct PathAgent {
+    pub cursor: PathCursor,
+    /// Rotate agent to follow the path tangent.
+    pub orient_to_path: bool,
+    /// Rotation in radians added on top of the tangent angle.
+    pub angle_offset: f32,
+}
+
+impl PathAgent {
+    pub fn new(t: f32) -> Self {
+        Self {
+            cursor: PathCursor::new(t),
+            ..Default::default()
         }
     }
 
-    pub fn tessellate_segments(&mut self) {
-        for i in 0..self.points.len() {
-            let neighbors = self.get_neigbors_positions(i);
-            self.points[i].segment.tessellate(neighbors.as_ref());
+    pub fn oriented(mut self, angle_offset: f32) -> Self {
+        self.orient_to_path = true;
+        self.angle_offset = angle_offset;
+        self
+    }
+}
+
+/// Path with its points and agents, as it is written in level files.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PathDef {
+    #[serde(default)]
+    pub mode: PathMode,
+    #[serde(default = "default_move_speed")]
+    pub move_speed: f32,
+    pub points: Vec<PathPointDef>,
+    #[serde(default)]
+    pub agents: Vec<PathAgentDef>,
+    /// How closely segments follow the curve, the default one is used when left out.
+    #[serde(default)]
+    pub tolerance: Option<TessellationTolerance>,
+}
+
+impl PathDef {
+    /// Curve agents of the path follow, for use outside of the game.
+    pub fn curve(&self) -> reggle_path::Path {
+        let mut curve = reggle_path::Path::new(self.move_speed, self.mode);
+        curve.tolerance = self.tolerance.unwrap_or_default();
+        curve.points = self
+            .points
+            .iter()
+            .map(|p| p.point().as_waypoint(Vec2::from(p.position).extend(0.0)))
+            .collect();
+        curve.tessellate_segments();
+        curve
+    }
+}
+
+fn default_move_speed() -> f32 {
+    100.0
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PathPointDef {
+    pub position: (f32, f32),
+    #[serde(default)]
+    pub segment_type: SegmentType,
+    #[serde(default = "default_speed_multiplier")]
+    pub speed_multiplier: f32,
+    #[serde(default)]
+    pub easing_function: PathEasingFunction,
+    #[serde(default)]
+    pub pause: f32,
+}
+
+fn default_speed_multiplier() -> f32 {
+    1.0
+}
+
+impl PathPointDef {
+    pub fn new(position: Vec2) -> Self {
+        Self {
+            position: position.into(),
+            segment_type: Default::default(),
+            speed_multiplier: 1.0,
+            easing_function: Default::default(),
+            pause: 0.0,
         }
     }
 
-    fn get_neigbors_positions(&self, point_idx: usize) -> Option<[Vec2; 4]> {
-        if !self.looped && point_idx >= self.points.len() - 1 {
-            return None;
+    fn point(&self) -> PathPoint {
+        PathPoint {
+            segment_type: self.segment_type,
+            speed_multiplier: self.speed_multiplier,
+            easing_function: self.easing_function,
+            pause: self.pause,
         }
-        let p1 = self.points[point_idx].pos;
-        let p2 = self.points[(point_idx + 1) % self.points.len()].pos;
-        let (p0, p3) = if self.looped {
-            (
-                self.points[point_idx
-                    .checked_add_signed(-1)
-                    .unwrap_or(self.points.len() - 1)]
-                .pos,
-                self.points[(point_idx + 2) % self.points.len()].pos,
-            )
-        } else {
-            let dir = (p2 - p1).normalize_or_zero();
-            let p0 = if point_idx > 0 {
-                self.points[point_idx - 1].pos
-            } else {
-                p1 - dir
-            };
-            let p3 = if point_idx < self.points.len() - 2 {
-                self.points[point_idx + 2].pos
-            } else {
-                p2 + dir
-            };
-            (p0, p3)
-        };
-        Some([p0, p1, p2, p3])
     }
 
-    pub fn move_agent_along_path(&self, mut t: f32, time_delta: f32) -> f32 {
-        let mut point_idx = (t.floor() as usize).clamp(0, self.points.len() - 1);
-        t = t.fract();
+    pub fn bundle(&self) -> (PathPoint, TransformBundle, Name) {
+        let position = Vec2::from(self.position).extend(0.0);
+        let transform = TransformBundle::from_transform(Transform::from_translation(position));
+        (self.point(), transform, Name::new("PathPoint"))
+    }
+}
 
-        let mut agent_distance = self.move_speed * time_delta;
-        loop {
-            let segment = &self.points[point_idx].segment;
-            let seg_distance = (1.0 - t) * segment.len();
-            if seg_distance < f32::EPSILON || seg_distance.is_nan() {
-                break;
-            }
-            if seg_distance > agent_distance {
-                t += agent_distance / segment.len();
-                break;
-            }
-            agent_distance -= seg_distance;
-            t = 0.0;
-            point_idx += 1;
-            if point_idx >= self.points.len() {
-                if self.looped {
-                    point_idx = 0
-                } else {
-                    break;
-                }
-            }
+/// Peg moving along the path.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PathAgentDef {
+    /// Starting position, see [`PathCursor::t`].
+    pub t: f32,
+    #[serde(default = "default_agent_peg_type")]
+    pub peg_type: String,
+    /// Rotation on top of the path tangent, in degrees. Agents keep their rotation if unset.
+    #[serde(default)]
+    pub orientation: Option<f32>,
+}
+
+fn default_agent_peg_type() -> String {
+    "rect".to_string()
+}
+
+impl PathAgentDef {
+    pub fn bundle(&self) -> (PegBundle, PathAgent) {
+        let mut agent = PathAgent::new(self.t);
+        if let Some(angle_offset) = self.orientation {
+            agent = agent.oriented(angle_offset.to_radians());
         }
-        t + point_idx as f32
+        let peg = PegBundle {
+            peg: Peg::new(&self.peg_type),
+            // Kinematic, so Rapier knows the velocity pegs are moving with
+            body: RigidBody::KinematicPositionBased,
+            ..Default::default()
+        };
+        (peg, agent)
     }
 }
 
-#[derive(Component, Reflect)]
-pub struct PathAgent {
-    pub t: f32,
+pub fn spawn_path(commands: &mut Commands, def: &PathDef) -> Entity {
+    let mut path = Path::new(def.move_speed, def.mode);
+    path.tolerance = def.tolerance.unwrap_or_default();
+    commands
+        .spawn((
+            path,
+            TransformBundle::default(),
+            VisibilityBundle::default(),
+            Name::new("Path"),
+        ))
+        .with_children(|cb| {
+            for point in def.points.iter() {
+                cb.spawn(point.bundle());
+            }
+            for agent in def.agents.iter() {
+                cb.spawn(agent.bundle());
+            }
+        })
+        .id()
 }
 
 fn move_path_agents(
+    mut commands: Commands,
     paths: Query<&Path>,
-    mut agents: Query<(&mut PathAgent, &mut Transform, &Parent)>,
+    mut agents: Query<(Entity, &mut PathAgent, &mut Transform, &Parent)>,
     time: Res<Time>,
+    in_game_state: Res<State<InGameState>>,
+    mut point_events: EventWriter<PathPointReached>,
+    mut loop_events: EventWriter<PathLoopCompleted>,
+    mut steps: Local<Vec<AgentStep>>,
 ) {
-    for (mut agent, mut tr, path) in agents.iter_mut() {
-        let Ok(path) = paths.get(path.get()) else {continue};
+    // Agents stay where they are placed while the level is edited, but follow edits of the path
+    let paused = *in_game_state.get() == InGameState::Editor;
+    for (entity, mut agent, mut tr, parent) in agents.iter_mut() {
+        let Ok(path) = paths.get(parent.get()) else {continue};
         if !path.is_valid() {
             continue;
         }
 
-        agent.t = path.move_agent_along_path(agent.t, time.delta_seconds());
-        tr.translation = path.get_world_pos(agent.t).extend(tr.translation.z)
+        if !paused {
+            steps.clear();
+            let delta = time.delta_seconds();
+            let alive = path.move_agent_along_path(&mut agent.cursor, delta, &mut steps);
+            for step in steps.iter() {
+                match *step {
+                    AgentStep::ReachedPoint(idx) => point_events.send(PathPointReached {
+                        agent: entity,
+                        path: parent.get(),
+                        point: path.point_entities[idx],
+                    }),
+                    AgentStep::CompletedLoop => loop_events.send(PathLoopCompleted {
+                        agent: entity,
+                        path: parent.get(),
+                    }),
+                }
+            }
+            if !alive {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+        }
+        let (pos, tangent) = path.get_world_pos_and_vel(agent.cursor.t);
+        tr.translation = pos.extend(tr.translation.z);
+        if agent.orient_to_path && tangent != Vec2::ZERO {
+            let angle = tangent.y.atan2(tangent.x) + agent.angle_offset;
+            tr.rotation = Quat::from_rotation_z(angle);
+        }
     }
 }
 
@@ -223,11 +359,12 @@ fn sync_path_points(
 ) {
     for (point_e, point, tr, parent) in changed_points.iter() {
         let Ok(mut path) = paths.get_mut(parent.get()) else {continue};
-        let new_raw_point = point.as_raw(tr.translation, point_e);
-        if let Some(raw_point) = path.points.iter_mut().find(|p| p.entity == point_e) {
-            *raw_point = new_raw_point;
+        let waypoint = point.as_waypoint(tr.translation);
+        if let Some(idx) = path.point_entities.iter().position(|&e| e == point_e) {
+            path.curve.points[idx] = waypoint;
         } else {
-            path.points.push(new_raw_point);
+            path.curve.points.push(waypoint);
+            path.point_entities.push(point_e);
         };
     }
     for removed_point in removed_points.iter() {
@@ -236,11 +373,12 @@ fn sync_path_points(
             // Avoid triggering change detection unless point is actually removed
             if let Some(idx) = path
                 .bypass_change_detection()
-                .points
+                .point_entities
                 .iter()
-                .position(|e| e.entity == removed_point)
+                .position(|&e| e == removed_point)
             {
-                path.points.remove(idx);
+                path.curve.points.remove(idx);
+                path.point_entities.remove(idx);
             }
         }
     }
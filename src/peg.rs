@@ -1,30 +1,49 @@
 use bevy::prelude::*;
-use bevy::utils::HashMap;
-use bevy_kira_audio::{Audio, AudioControl};
+use bevy_prototype_lyon::prelude::{Fill, GeometryBuilder, ShapeBundle};
+use bevy_prototype_lyon::shapes::Circle;
 use bevy_rapier2d::prelude::*;
 use bevy_tweening::{Animator, EaseFunction, Lens, Tween};
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::time::Duration;
 
+use crate::ball::Ball;
 use crate::common::{GameState, GameStats, InGameState};
-use crate::path::{Path, PathAgent, PathPoint};
-use crate::sounds::{play_collision_sound, CollisionSound, SoundType};
+use crate::peg_type::{Explosion, PegTypeDefs, PegTypeDefsLoader, PegTypeRegistry};
+use crate::sounds::{play_collision_sound, AudioChannels, CollisionSound, SoundBank};
 use crate::{assets::GameAssets, PEG_RADIUS};
 
+/// Peg types random layouts pick from, as (normal, target) pairs.
+pub const GRID_PEG_TYPES: [(&str, &str); 4] = [
+    ("round", "round_target"),
+    ("rect", "rect_target"),
+    ("armored", "armored_target"),
+    ("explosive", "round_target"),
+];
+const SHOCKWAVE_SECS: f32 = 0.3;
+
 pub struct PegPlugin;
 
 impl Plugin for PegPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<PegDespawnEvent>()
             .insert_resource(PegDespawnQueue::default())
-            .add_systems(OnEnter(GameState::InGame), spawn_peg_system)
+            .add_asset::<PegTypeDefs>()
+            .init_asset_loader::<PegTypeDefsLoader>()
+            .init_resource::<PegTypeRegistry>()
+            .add_systems(OnEnter(GameState::InGame), rebuild_peg_types)
+            .add_systems(OnExit(GameState::InGame), despawn_shockwaves)
             .add_systems(
                 Update,
                 (
+                    (rebuild_peg_types, refresh_pegs)
+                        .chain()
+                        .run_if(resource_changed::<GameAssets>()),
                     transform_peg,
                     peg_hit_system
                         .run_if(in_state(InGameState::Ball))
                         .after(play_collision_sound),
+                    animate_shockwaves,
                     peg_cleanup.run_if(in_state(InGameState::Cleanup)),
                     peg_despawn.after(peg_cleanup),
                 )
@@ -38,29 +57,71 @@ pub struct PegDespawnEvent(pub Entity);
 
 #[derive(Default, Bundle, Clone)]
 pub struct PegPreset {
-    img: Handle<Image>,
-    sprite: Sprite,
-    collision_sound: CollisionSound,
-    collider: Collider,
+    pub img: Handle<Image>,
+    pub sprite: Sprite,
+    pub collision_sound: CollisionSound,
+    pub collider: Collider,
 }
 
-#[derive(Default, PartialEq, Eq, Hash)]
+#[derive(Default, PartialEq, Eq, Hash, Clone, Copy, Debug, Deserialize)]
 pub enum PegState {
     #[default]
     Active,
+    /// Peg was hit this many times, but still has hit points left.
+    Damaged(u32),
     Hit,
 }
 
+impl PegState {
+    /// State after one more hit on a peg with `hit_points` in total.
+    pub fn after_hit(&self, hit_points: u32) -> Self {
+        let hits = match *self {
+            PegState::Active => 1,
+            PegState::Damaged(hits) => hits + 1,
+            PegState::Hit => return PegState::Hit,
+        };
+        if hits >= hit_points {
+            PegState::Hit
+        } else {
+            PegState::Damaged(hits)
+        }
+    }
+
+    /// State whose preset is used when this one doesn't have its own.
+    pub fn fallback(&self) -> Option<Self> {
+        match *self {
+            PegState::Active | PegState::Hit => None,
+            PegState::Damaged(1) => Some(PegState::Active),
+            PegState::Damaged(hits) => Some(PegState::Damaged(hits - 1)),
+        }
+    }
+}
+
 #[derive(Component, Default)]
 pub struct Peg {
-    pub is_target: bool,
+    /// Id of the peg type in [`PegTypeRegistry`].
+    pub peg_type: String,
     pub state: PegState,
-    pub presets: std::sync::Arc<HashMap<PegState, PegPreset>>,
+}
+
+impl Peg {
+    pub fn new(peg_type: &str) -> Self {
+        Self {
+            peg_type: peg_type.to_string(),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Resource, Default)]
 pub struct PegDespawnQueue(VecDeque<Entity>);
 
+#[derive(Component)]
+struct Shockwave {
+    radius: f32,
+    timer: Timer,
+}
+
 #[derive(Bundle)]
 pub struct PegBundle {
     #[bundle()]
@@ -92,67 +153,60 @@ impl Default for PegBundle {
     }
 }
 
-fn spawn_peg_system(mut commands: Commands, game_assets: Res<GameAssets>) {
-    let pegs_count = 15 * 7;
-    let target_pegs_count = pegs_count / 8;
+/// Single static peg, as it is written in level files.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PegDef {
+    pub position: (f32, f32),
+    /// Counterclockwise, in degrees.
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "default_peg_type")]
+    pub peg_type: String,
+}
 
-    let mut round_peg_presets = HashMap::new();
-    let mut round_target_peg_presets = HashMap::new();
-    let mut round_peg_preset = PegPreset {
-        img: game_assets.peg.image.clone(),
-        sprite: Sprite {
-            custom_size: Some(Vec2::new(PEG_RADIUS * 2.0, PEG_RADIUS * 2.0)),
-            ..Default::default()
-        },
-        collision_sound: CollisionSound {
-            sound: SoundType::Random(game_assets.peg.hit_sound.clone()),
-            ..Default::default()
-        },
-        collider: Collider::ball(PEG_RADIUS),
-    };
-    let mut round_target_peg_preset = round_peg_preset.clone();
-    round_target_peg_preset.sprite.color = Color::ORANGE;
-    round_peg_presets.insert(PegState::Active, round_peg_preset.clone());
-    round_target_peg_presets.insert(PegState::Active, round_target_peg_preset.clone());
-
-    round_peg_preset.collision_sound.sound = SoundType::None;
-    round_peg_preset.sprite.color = Color::MIDNIGHT_BLUE;
-    round_target_peg_preset.collision_sound.sound = SoundType::None;
-    round_target_peg_preset.sprite.color = Color::ORANGE_RED;
-    round_peg_presets.insert(PegState::Hit, round_peg_preset);
-    round_target_peg_presets.insert(PegState::Hit, round_target_peg_preset);
-    let round_peg_presets = std::sync::Arc::new(round_peg_presets);
-    let round_target_peg_presets = std::sync::Arc::new(round_target_peg_presets);
-
-    let mut rect_peg_presets = HashMap::new();
-    let mut rect_target_peg_presets = HashMap::new();
-    let mut rect_peg_preset = PegPreset {
-        img: game_assets.peg.image.clone(),
-        sprite: Sprite {
-            custom_size: Some(Vec2::new(PEG_RADIUS * 2.0 * 1.5, PEG_RADIUS * 2.0)),
-            ..Default::default()
-        },
-        collision_sound: CollisionSound {
-            sound: SoundType::Random(game_assets.peg.hit_sound.clone()),
+fn default_peg_type() -> String {
+    "round".to_string()
+}
+
+impl PegDef {
+    pub fn bundle(&self) -> PegBundle {
+        let position = Vec2::from(self.position).extend(0.0);
+        PegBundle {
+            peg: Peg::new(&self.peg_type),
+            sprite_bundle: SpriteBundle {
+                transform: Transform::from_translation(position)
+                    .with_rotation(Quat::from_rotation_z(self.rotation.to_radians())),
+                ..Default::default()
+            },
             ..Default::default()
-        },
-        collider: Collider::cuboid(PEG_RADIUS * 1.5, PEG_RADIUS),
-    };
-    let mut rect_target_peg_preset = rect_peg_preset.clone();
-    rect_target_peg_preset.sprite.color = Color::GREEN;
-    rect_peg_presets.insert(PegState::Active, rect_peg_preset.clone());
-    rect_target_peg_presets.insert(PegState::Active, rect_target_peg_preset.clone());
-
-    rect_peg_preset.collision_sound.sound = SoundType::None;
-    rect_peg_preset.sprite.color = Color::MIDNIGHT_BLUE;
-    rect_target_peg_preset.collision_sound.sound = SoundType::None;
-    rect_target_peg_preset.sprite.color = Color::DARK_GREEN;
-    rect_peg_presets.insert(PegState::Hit, rect_peg_preset);
-    rect_target_peg_presets.insert(PegState::Hit, rect_target_peg_preset);
-    let rect_peg_presets = std::sync::Arc::new(rect_peg_presets);
-    let rect_target_peg_presets = std::sync::Arc::new(rect_target_peg_presets);
+        }
+    }
+}
+
+pub fn rebuild_peg_types(
+    game_assets: Res<GameAssets>,
+    peg_type_defs: Res<Assets<PegTypeDefs>>,
+    sound_banks: Res<Assets<SoundBank>>,
+    mut registry: ResMut<PegTypeRegistry>,
+) {
+    let Some(defs) = peg_type_defs.get(&game_assets.peg.types) else {return};
+    *registry = PegTypeRegistry::new(defs, &game_assets, sound_banks.get(&game_assets.sound_bank));
+}
+
+/// Reapplies presets to every peg after the registry was rebuilt.
+fn refresh_pegs(mut pegs: Query<&mut Peg>) {
+    for mut peg in pegs.iter_mut() {
+        peg.set_changed();
+    }
+}
+
+/// Grid of pegs with random types, used by levels that have no pegs of their own.
+pub fn grid_pegs() -> Vec<PegBundle> {
+    let pegs_count = 15 * 7;
+    let target_pegs_count = pegs_count / 8;
 
     let mut pegs = Vec::with_capacity(pegs_count);
+    let mut grid_peg_types = Vec::with_capacity(pegs_count);
     for i in 0..15 {
         for j in 1..8 {
             let tr = Transform::from_xyz(
@@ -160,82 +214,33 @@ fn spawn_peg_system(mut commands: Commands, game_assets: Res<GameAssets>) {
                 -j as f32 * PEG_RADIUS * 5.0,
                 0.0,
             );
-            let presets = fastrand::choice([&round_peg_presets, &rect_peg_presets])
-                .unwrap()
-                .clone();
+            let peg_types = *fastrand::choice(&GRID_PEG_TYPES).unwrap();
             pegs.push(PegBundle {
-                peg: Peg {
-                    presets,
-                    ..Default::default()
-                },
+                peg: Peg::new(peg_types.0),
                 sprite_bundle: SpriteBundle {
                     transform: tr,
                     ..Default::default()
                 },
                 body: RigidBody::Fixed,
                 ..Default::default()
-            })
+            });
+            grid_peg_types.push(peg_types);
         }
     }
 
-    fastrand::shuffle(&mut pegs);
-
-    for (i, peg) in pegs.iter_mut().enumerate() {
-        if i < target_pegs_count {
-            if std::sync::Arc::ptr_eq(&peg.peg.presets, &round_peg_presets) {
-                peg.peg.presets = round_target_peg_presets.clone();
-            } else {
-                peg.peg.presets = rect_target_peg_presets.clone();
-            }
-        }
+    let mut order: Vec<_> = (0..pegs.len()).collect();
+    fastrand::shuffle(&mut order);
+    for &i in order.iter().take(target_pegs_count) {
+        pegs[i].peg = Peg::new(grid_peg_types[i].1);
     }
-
-    commands.spawn_batch(pegs.into_iter());
-
-    commands
-        .spawn((
-            Path::new(100.0, true),
-            TransformBundle::default(),
-            VisibilityBundle::default(),
-        ))
-        .with_children(|cb| {
-            for i in [
-                Vec2::new(-300.0, 300.0),
-                Vec2::new(300.0, 300.0),
-                Vec2::new(300.0, -300.0),
-                Vec2::new(-300.0, -300.0),
-            ] {
-                cb.spawn((
-                    PathPoint::default(),
-                    TransformBundle::from_transform(Transform::from_translation(i.extend(0.0))),
-                ));
-            }
-            for i in 0..20 {
-                cb.spawn((
-                    PegBundle {
-                        peg: Peg {
-                            presets: rect_peg_presets.clone(),
-                            ..Default::default()
-                        },
-                        sprite_bundle: SpriteBundle {
-                            ..Default::default()
-                        },
-                        body: RigidBody::KinematicPositionBased,
-                        ..Default::default()
-                    },
-                    PathAgent {
-                        t: 4.0 * i as f32 / 20.0,
-                    },
-                ));
-            }
-        });
+    pegs
 }
 
 fn peg_cleanup(
     mut commands: Commands,
     time: Res<Time>,
     mut despawn_events: EventWriter<PegDespawnEvent>,
-    mut peg_sprites: Query<&mut Sprite, With<Peg>>,
+    mut peg_sprites: Query<(Option<&mut Sprite>, &mut Transform), With<Peg>>,
     mut despawn_timer: Local<Option<Timer>>,
     mut peg_despawn_queue: ResMut<PegDespawnQueue>,
 ) {
@@ -245,8 +250,13 @@ fn peg_cleanup(
     loop {
         if let Some(peg) = peg_despawn_queue.0.front() {
             let inflated_size = PEG_RADIUS * 2.0 + despawn_timer.percent() * PEG_RADIUS * 1.5;
-            if let Ok(mut s) = peg_sprites.get_mut(*peg) {
-                s.custom_size = Some(Vec2::splat(inflated_size));
+            if let Ok((sprite, mut tr)) = peg_sprites.get_mut(*peg) {
+                if let Some(mut s) = sprite {
+                    s.custom_size = Some(Vec2::splat(inflated_size));
+                } else {
+                    // Bricks have no sprite, so they are inflated by scale instead
+                    tr.scale = Vec3::splat(inflated_size / (PEG_RADIUS * 2.0));
+                }
             } else {
                 // Skips already depspawned entities
                 peg_despawn_queue.0.pop_front();
@@ -269,17 +279,21 @@ fn peg_despawn(
     mut commands: Commands,
     mut despawn_events: EventReader<PegDespawnEvent>,
     mut game_stats: ResMut<GameStats>,
-    pegs: Query<Entity, With<Peg>>,
+    pegs: Query<&Peg>,
+    registry: Res<PegTypeRegistry>,
     game_assets: Res<GameAssets>,
-    audio: Res<Audio>,
+    channels: AudioChannels,
 ) {
     for PegDespawnEvent(entity) in despawn_events.iter() {
-        if !pegs.contains(*entity) {
-            continue;
-        }
+        let Ok(peg) = pegs.get(*entity) else {continue};
         let Some(mut entity_commands) = commands.get_entity(*entity) else {continue};
-        audio.play(game_assets.peg.pop_sound.clone());
-        game_stats.player_score += 1;
+        game_assets.peg.pop_sound.play(&channels, 1.0, 0.5);
+        if let Some(peg_type) = registry.get(&peg.peg_type) {
+            game_stats.player_score += peg_type.score;
+            if peg_type.is_target {
+                game_stats.target_pegs_left = game_stats.target_pegs_left.saturating_sub(1);
+            }
+        }
         entity_commands.despawn();
     }
 }
@@ -287,8 +301,14 @@ fn peg_despawn(
 fn peg_hit_system(
     mut commands: Commands,
     mut collision_events: EventReader<CollisionEvent>,
-    mut pegs: Query<(Entity, &Sprite, &mut Peg)>,
+    mut pegs: Query<(Entity, &mut Peg)>,
+    registry: Res<PegTypeRegistry>,
     mut peg_despawn_queue: ResMut<PegDespawnQueue>,
+    rapier_ctx: Res<RapierContext>,
+    transforms: Query<&GlobalTransform>,
+    mut balls: Query<&mut Velocity, With<Ball>>,
+    game_assets: Res<GameAssets>,
+    channels: AudioChannels,
 ) {
     for event in collision_events.iter() {
         let CollisionEvent::Started(e1, e2, _) = event else {continue};
@@ -296,11 +316,12 @@ fn peg_hit_system(
         if comps.is_err() {
             comps = pegs.get_mut(*e2);
         }
-        let Ok((entity, sprite, mut peg)) = comps else {continue};
+        let Ok((entity, mut peg)) = comps else {continue};
 
         if peg.state == PegState::Hit {
             continue;
         }
+        let Some(peg_type) = registry.get(&peg.peg_type) else {continue};
 
         struct SpriteSizeLens {
             start: Vec2,
@@ -313,34 +334,159 @@ fn peg_hit_system(
             }
         }
 
-        peg.state = PegState::Hit;
+        peg.state = peg.state.after_hit(peg_type.hit_points);
+        // Sprite may still be inflated by the previous hit
+        let size = peg_type.shape.size();
         let hit_tween = Tween::new(
             EaseFunction::CubicIn,
             Duration::from_secs_f32(0.1),
             SpriteSizeLens {
-                start: sprite.custom_size.unwrap_or_default(),
-                end: sprite.custom_size.unwrap_or_default() * 1.5,
+                start: size,
+                end: size * 1.5,
             },
         )
         .then(Tween::new(
             EaseFunction::CubicOut,
             Duration::from_secs_f32(0.1),
             SpriteSizeLens {
-                end: sprite.custom_size.unwrap_or_default(),
-                start: sprite.custom_size.unwrap_or_default() * 1.5,
+                end: size,
+                start: size * 1.5,
             },
         ));
         commands.entity(entity).insert(Animator::new(hit_tween));
-        peg_despawn_queue.0.push_back(entity);
+        if peg.state == PegState::Hit {
+            peg_despawn_queue.0.push_back(entity);
+            if peg_type.explosion.is_some() {
+                let explosions = detonate(
+                    entity,
+                    &rapier_ctx,
+                    &registry,
+                    &mut pegs,
+                    &transforms,
+                    &mut balls,
+                    &mut peg_despawn_queue,
+                );
+                for (center, explosion) in explosions {
+                    game_assets.peg.explode_sound.play(&channels, 1.0, 0.5);
+                    spawn_shockwave(&mut commands, center, explosion.radius);
+                }
+            }
+        }
+    }
+}
+
+/// Detonates an explosive peg, hitting every peg in its radius and chaining into other
+/// explosive pegs. Returns centers of all explosions that happened.
+fn detonate(
+    origin: Entity,
+    rapier_ctx: &RapierContext,
+    registry: &PegTypeRegistry,
+    pegs: &mut Query<(Entity, &mut Peg)>,
+    transforms: &Query<&GlobalTransform>,
+    balls: &mut Query<&mut Velocity, With<Ball>>,
+    peg_despawn_queue: &mut PegDespawnQueue,
+) -> Vec<(Vec2, Explosion)> {
+    let mut explosions = Vec::new();
+    let mut pending = VecDeque::from([origin]);
+    while let Some(entity) = pending.pop_front() {
+        let Ok((_, peg)) = pegs.get(entity) else {continue};
+        let explosion = registry.get(&peg.peg_type).and_then(|t| t.explosion);
+        let Some(explosion) = explosion else {continue};
+        let Ok(tr) = transforms.get(entity) else {continue};
+        let center = tr.translation().truncate();
+        explosions.push((center, explosion));
+
+        let mut caught = Vec::new();
+        rapier_ctx.intersections_with_shape(
+            center,
+            0.0,
+            &Collider::ball(explosion.radius),
+            QueryFilter::default(),
+            |e| {
+                caught.push(e);
+                true
+            },
+        );
+        for e in caught {
+            if let Ok((_, mut peg)) = pegs.get_mut(e) {
+                if peg.state == PegState::Hit {
+                    continue;
+                }
+                peg.state = PegState::Hit;
+                peg_despawn_queue.0.push_back(e);
+                if registry
+                    .get(&peg.peg_type)
+                    .map_or(false, |t| t.explosion.is_some())
+                {
+                    pending.push_back(e);
+                }
+            } else if let (Ok(mut vel), Ok(ball_tr)) = (balls.get_mut(e), transforms.get(e)) {
+                let offset = ball_tr.translation().truncate() - center;
+                let falloff = (1.0 - offset.length() / explosion.radius).max(0.0);
+                vel.linvel += offset.normalize_or_zero() * explosion.shockwave_speed * falloff;
+            }
+        }
+    }
+    explosions
+}
+
+fn spawn_shockwave(commands: &mut Commands, center: Vec2, radius: f32) {
+    let circle = Circle {
+        radius: 1.0,
+        center: Vec2::ZERO,
+    };
+    commands.spawn((
+        ShapeBundle {
+            path: GeometryBuilder::build_as(&circle),
+            transform: Transform::from_translation(center.extend(1.0)).with_scale(Vec3::ZERO),
+            ..Default::default()
+        },
+        Fill::color(Color::rgba(1.0, 1.0, 1.0, 0.5)),
+        Shockwave {
+            radius,
+            timer: Timer::from_seconds(SHOCKWAVE_SECS, TimerMode::Once),
+        },
+    ));
+}
+
+fn animate_shockwaves(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut shockwaves: Query<(Entity, &mut Shockwave, &mut Transform, &mut Fill)>,
+) {
+    for (entity, mut shockwave, mut tr, mut fill) in shockwaves.iter_mut() {
+        shockwave.timer.tick(time.delta());
+        let t = shockwave.timer.percent();
+        tr.scale = Vec3::splat(shockwave.radius * t);
+        fill.color.set_a(0.5 * (1.0 - t));
+        if shockwave.timer.finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn despawn_shockwaves(mut commands: Commands, shockwaves: Query<Entity, With<Shockwave>>) {
+    for entity in shockwaves.iter() {
+        commands.entity(entity).despawn();
     }
 }
 
 fn transform_peg(
     mut commands: Commands,
-    mut pegs: Query<(Entity, &Peg), Or<(Added<Peg>, Changed<Peg>)>>,
+    registry: Res<PegTypeRegistry>,
+    mut pegs: Query<(Entity, &Peg, Option<&mut Fill>), Or<(Added<Peg>, Changed<Peg>)>>,
 ) {
-    pegs.iter_mut().for_each(|(e, peg)| {
-        let Some(preset) = peg.presets.get(&peg.state) else {return};
-        commands.entity(e).insert(preset.clone());
+    pegs.iter_mut().for_each(|(e, peg, fill)| {
+        let preset = registry
+            .get(&peg.peg_type)
+            .and_then(|t| t.presets.get(&peg.state));
+        let Some(preset) = preset else {return};
+        // Bricks keep their own wedge shape and only take the color and sound
+        if let Some(mut fill) = fill {
+            fill.color = preset.sprite.color;
+            commands.entity(e).insert(preset.collision_sound.clone());
+        } else {
+            commands.entity(e).insert(preset.clone());
+        }
     });
 }
@@ -0,0 +1,313 @@
+use bevy::asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::{BoxedFuture, HashMap};
+use bevy_rapier2d::prelude::Collider;
+use serde::Deserialize;
+
+use crate::assets::GameAssets;
+use crate::peg::{PegPreset, PegState};
+use crate::sounds::{CollisionSound, SoundBank};
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum PegShape {
+    Circle {
+        radius: f32,
+    },
+    Rect {
+        half_width: f32,
+        half_height: f32,
+    },
+    /// Wedge laid along a curve. Its real outline is generated per brick, so this only
+    /// describes a nominal square brick.
+    Brick {
+        thickness: f32,
+    },
+}
+
+impl PegShape {
+    pub fn size(&self) -> Vec2 {
+        match *self {
+            PegShape::Circle { radius } => Vec2::splat(radius * 2.0),
+            PegShape::Rect {
+                half_width,
+                half_height,
+            } => Vec2::new(half_width * 2.0, half_height * 2.0),
+            PegShape::Brick { thickness } => Vec2::splat(thickness),
+        }
+    }
+
+    pub fn collider(&self) -> Collider {
+        match *self {
+            PegShape::Circle { radius } => Collider::ball(radius),
+            PegShape::Rect {
+                half_width,
+                half_height,
+            } => Collider::cuboid(half_width, half_height),
+            PegShape::Brick { thickness } => Collider::cuboid(thickness / 2.0, thickness / 2.0),
+        }
+    }
+}
+
+/// Makes a peg detonate once it is hit, hitting every peg within `radius`.
+#[derive(Deserialize, Clone, Copy)]
+pub struct Explosion {
+    pub radius: f32,
+    /// Velocity pushed into balls at the center of the explosion, fading out towards its edge.
+    #[serde(default)]
+    pub shockwave_speed: f32,
+}
+
+/// Look of a peg type in one state. Anything left out is taken from the active theme.
+#[derive(Default)]
+pub struct PegStateLook {
+    pub image: Option<Handle<Image>>,
+    pub color: Option<Color>,
+    /// Name of the sound in the theme's sound bank.
+    pub sound: Option<String>,
+}
+
+pub struct PegTypeDef {
+    pub shape: PegShape,
+    pub is_target: bool,
+    pub score: usize,
+    pub hit_points: u32,
+    pub explosion: Option<Explosion>,
+    pub states: HashMap<PegState, PegStateLook>,
+}
+
+/// Peg types loaded from a `*.pegs.ron` file, keyed by id.
+#[derive(TypeUuid, TypePath, Default)]
+#[uuid = "2d7c9b14-6f3e-4a8b-9c05-e1f2a3b4c5d6"]
+pub struct PegTypeDefs(pub HashMap<String, PegTypeDef>);
+
+impl PegTypeDefs {
+    pub fn images(&self) -> impl Iterator<Item = &Handle<Image>> {
+        self.0
+            .values()
+            .flat_map(|t| t.states.values())
+            .filter_map(|s| s.image.as_ref())
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PegStateLookDef {
+    image: Option<String>,
+    color: Option<String>,
+    sound: Option<String>,
+}
+
+fn default_score() -> usize {
+    1
+}
+
+fn default_hit_points() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+struct PegTypeDefRaw {
+    shape: PegShape,
+    #[serde(default)]
+    is_target: bool,
+    #[serde(default = "default_score")]
+    score: usize,
+    #[serde(default = "default_hit_points")]
+    hit_points: u32,
+    #[serde(default)]
+    explosion: Option<Explosion>,
+    #[serde(default)]
+    states: HashMap<PegState, PegStateLookDef>,
+}
+
+/// Parts of a peg type that matter for collisions, see [`read_peg_shapes`].
+#[derive(Clone, Copy)]
+pub struct PegTypeShape {
+    pub shape: PegShape,
+    pub is_target: bool,
+}
+
+/// Reads shapes of the peg types in a `*.pegs.ron` file, without loading any of their assets.
+pub fn read_peg_shapes(path: &std::path::Path) -> Result<HashMap<String, PegTypeShape>, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let raw: HashMap<String, PegTypeDefRaw> =
+        ron::de::from_bytes(&bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(raw
+        .into_iter()
+        .map(|(id, raw_type)| {
+            let shape = PegTypeShape {
+                shape: raw_type.shape,
+                is_target: raw_type.is_target,
+            };
+            (id, shape)
+        })
+        .collect())
+}
+
+#[derive(Default)]
+pub struct PegTypeDefsLoader;
+
+impl AssetLoader for PegTypeDefsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let raw: HashMap<String, PegTypeDefRaw> = ron::de::from_bytes(bytes)?;
+            let mut dependencies = Vec::new();
+            let mut types = HashMap::new();
+            for (id, raw_type) in raw {
+                let mut states = HashMap::new();
+                for (state, look) in raw_type.states {
+                    let image = look.image.map(|path| {
+                        let asset_path = AssetPath::from(path.as_str()).to_owned();
+                        dependencies.push(asset_path.clone());
+                        load_context.get_handle(asset_path)
+                    });
+                    let color = look.color.map(Color::hex).transpose()?;
+                    let look = PegStateLook {
+                        image,
+                        color,
+                        sound: look.sound,
+                    };
+                    states.insert(state, look);
+                }
+                let peg_type = PegTypeDef {
+                    shape: raw_type.shape,
+                    is_target: raw_type.is_target,
+                    score: raw_type.score,
+                    hit_points: raw_type.hit_points.max(1),
+                    explosion: raw_type.explosion,
+                    states,
+                };
+                types.insert(id, peg_type);
+            }
+            load_context.set_default_asset(
+                LoadedAsset::new(PegTypeDefs(types)).with_dependencies(dependencies),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["pegs.ron"]
+    }
+}
+
+pub struct PegType {
+    pub shape: PegShape,
+    pub is_target: bool,
+    pub score: usize,
+    pub hit_points: u32,
+    pub explosion: Option<Explosion>,
+    pub presets: HashMap<PegState, PegPreset>,
+}
+
+impl PegTypeDef {
+    /// Every state a peg of this type can be in.
+    pub fn all_states(&self) -> impl Iterator<Item = PegState> {
+        std::iter::once(PegState::Active)
+            .chain((1..self.hit_points).map(PegState::Damaged))
+            .chain(std::iter::once(PegState::Hit))
+    }
+
+    /// Look of the state, falling back to earlier states if it has none.
+    fn look(&self, mut state: PegState) -> Option<&PegStateLook> {
+        loop {
+            if let Some(look) = self.states.get(&state) {
+                return Some(look);
+            }
+            state = state.fallback()?;
+        }
+    }
+}
+
+/// Every peg type with presets built from the active theme.
+#[derive(Resource, Default)]
+pub struct PegTypeRegistry(HashMap<String, PegType>);
+
+impl PegTypeRegistry {
+    pub fn new(
+        defs: &PegTypeDefs,
+        game_assets: &GameAssets,
+        sound_bank: Option<&SoundBank>,
+    ) -> Self {
+        let types = defs
+            .0
+            .iter()
+            .map(|(id, def)| {
+                let default_look = PegStateLook::default();
+                let presets = def
+                    .all_states()
+                    .map(|state| {
+                        let look = def.look(state).unwrap_or(&default_look);
+                        let preset =
+                            Self::build_preset(id, def, state, look, game_assets, sound_bank);
+                        (state, preset)
+                    })
+                    .collect();
+                let peg_type = PegType {
+                    shape: def.shape,
+                    is_target: def.is_target,
+                    score: def.score,
+                    hit_points: def.hit_points,
+                    explosion: def.explosion,
+                    presets,
+                };
+                (id.clone(), peg_type)
+            })
+            .collect();
+        Self(types)
+    }
+
+    fn build_preset(
+        id: &str,
+        def: &PegTypeDef,
+        state: PegState,
+        look: &PegStateLook,
+        game_assets: &GameAssets,
+        sound_bank: Option<&SoundBank>,
+    ) -> PegPreset {
+        let theme_colors = game_assets.peg.colors.get(id);
+        let (theme_image, theme_color) = match state {
+            PegState::Active => (&game_assets.peg.image, theme_colors.map(|c| c.active)),
+            PegState::Damaged(hits) => {
+                // Fade from active towards hit color with every hit taken
+                let ratio = hits as f32 / def.hit_points as f32;
+                let color = theme_colors.map(|c| lerp_color(c.active, c.hit, ratio));
+                (&game_assets.peg.image, color)
+            }
+            PegState::Hit => (&game_assets.peg.hit_image, theme_colors.map(|c| c.hit)),
+        };
+        let collision_sound = match (&look.sound, sound_bank) {
+            (Some(sound), Some(bank)) => bank.get(sound),
+            _ => CollisionSound::default(),
+        };
+        PegPreset {
+            img: look.image.clone().unwrap_or_else(|| theme_image.clone()),
+            sprite: Sprite {
+                custom_size: Some(def.shape.size()),
+                color: look.color.or(theme_color).unwrap_or(Color::WHITE),
+                ..Default::default()
+            },
+            collision_sound,
+            collider: def.shape.collider(),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&PegType> {
+        self.0.get(id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PegType)> {
+        self.0.iter().map(|(id, t)| (id.as_str(), t))
+    }
+}
+
+fn lerp_color(from: Color, to: Color, ratio: f32) -> Color {
+    let from = Vec4::from(from.as_rgba_f32());
+    let to = Vec4::from(to.as_rgba_f32());
+    Color::from(from.lerp(to, ratio))
+}
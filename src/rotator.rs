@@ -0,0 +1,138 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{GameState, InGameState};
+use crate::peg::{Peg, PegBundle};
+
+pub struct RotatorPlugin;
+
+impl Plugin for RotatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            rotate_system
+                .run_if(not(in_state(InGameState::Editor)))
+                .run_if(in_state(GameState::InGame)),
+        );
+    }
+}
+
+/// How a [`Rotator`] turns over time. Angles are in degrees.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum RotatorMotion {
+    /// Spins forever with `speed` degrees per second, counterclockwise if positive.
+    Constant { speed: f32 },
+    /// Swings between two angles with constant speed, taking `period` seconds for a full swing.
+    PingPong { from: f32, to: f32, period: f32 },
+    /// Same as `PingPong`, but slows down towards both ends of the swing.
+    Eased { from: f32, to: f32, period: f32 },
+}
+
+impl RotatorMotion {
+    /// Swing period in seconds, for motions that swing.
+    pub fn period(&self) -> Option<f32> {
+        match *self {
+            RotatorMotion::Constant { .. } => None,
+            RotatorMotion::PingPong { period, .. } | RotatorMotion::Eased { period, .. } => {
+                Some(period)
+            }
+        }
+    }
+
+    /// Angle in radians after `time` seconds.
+    pub fn angle(&self, time: f32) -> f32 {
+        let degrees = match *self {
+            RotatorMotion::Constant { speed } => speed * time,
+            RotatorMotion::PingPong { from, to, period } => {
+                let phase = swing_phase(time, period);
+                let ratio = 1.0 - (phase * 2.0 - 1.0).abs();
+                from + (to - from) * ratio
+            }
+            RotatorMotion::Eased { from, to, period } => {
+                let phase = swing_phase(time, period);
+                let ratio = (1.0 - (phase * TAU).cos()) / 2.0;
+                from + (to - from) * ratio
+            }
+        };
+        degrees.to_radians()
+    }
+}
+
+/// Fraction of the current swing, from 0.0 to 1.0. Swings without a positive period stay at the
+/// start, instead of turning the angle into NaN.
+fn swing_phase(time: f32, period: f32) -> f32 {
+    if period > 0.0 {
+        (time / period).fract()
+    } else {
+        0.0
+    }
+}
+
+/// Spins the entity with all its children around the entity position.
+#[derive(Component)]
+pub struct Rotator {
+    pub motion: RotatorMotion,
+    pub time: f32,
+}
+
+impl Rotator {
+    pub fn new(motion: RotatorMotion) -> Self {
+        Self { motion, time: 0.0 }
+    }
+}
+
+/// Group of pegs rotating around a pivot, as it is written in level files.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RotatorDef {
+    pub pivot: (f32, f32),
+    pub motion: RotatorMotion,
+    pub pegs: Vec<RotatorPegDef>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RotatorPegDef {
+    /// Position relative to the pivot.
+    pub offset: (f32, f32),
+    #[serde(default = "default_peg_type")]
+    pub peg_type: String,
+}
+
+fn default_peg_type() -> String {
+    "round".to_string()
+}
+
+pub fn spawn_rotator(commands: &mut Commands, def: &RotatorDef) {
+    let pivot = Vec2::from(def.pivot);
+    commands
+        .spawn((
+            Rotator::new(def.motion),
+            TransformBundle::from_transform(Transform::from_translation(pivot.extend(0.0))),
+            VisibilityBundle::default(),
+            Name::new("Rotator"),
+        ))
+        .with_children(|cb| {
+            for peg in def.pegs.iter() {
+                let offset = Vec2::from(peg.offset);
+                cb.spawn(PegBundle {
+                    peg: Peg::new(&peg.peg_type),
+                    sprite_bundle: SpriteBundle {
+                        transform: Transform::from_translation(offset.extend(0.0)),
+                        ..Default::default()
+                    },
+                    // Kinematic, so Rapier knows the velocity pegs are moving with
+                    body: RigidBody::KinematicPositionBased,
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+fn rotate_system(time: Res<Time>, mut rotators: Query<(&mut Rotator, &mut Transform)>) {
+    for (mut rotator, mut tr) in rotators.iter_mut() {
+        rotator.time += time.delta_seconds();
+        tr.rotation = Quat::from_rotation_z(rotator.motion.angle(rotator.time));
+    }
+}
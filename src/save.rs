@@ -0,0 +1,413 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::campaign::CampaignProgress;
+use crate::settings::Settings;
+
+const SAVE_KEY: &str = "save.ron";
+/// Where a save that can't be read is moved, before it is replaced by a new one.
+const BACKUP_KEY: &str = "save.ron.bak";
+/// Version written to new saves, bumped whenever [`SaveData`] changes incompatibly.
+const SAVE_VERSION: u32 = 1;
+/// Settings file written before saves existed, migrated into the first save.
+const LEGACY_SETTINGS_KEY: &str = "settings.ron";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let mut store = SaveStore::new();
+        let save = store.load();
+        app.insert_resource(save.settings)
+            .insert_resource(save.progress)
+            .insert_resource(store)
+            .add_systems(PostUpdate, write_save);
+    }
+}
+
+/// Storage the save is read from and written to, by key.
+pub trait SaveBackend: Send + Sync {
+    fn read(&self, key: &str) -> Result<Option<String>, String>;
+    /// Replaces the data under `key`, either completely or not at all.
+    fn write(&self, key: &str, data: &str) -> Result<(), String>;
+}
+
+/// Files in `dir`, the working directory by default.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileBackend {
+    pub dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SaveBackend for FileBackend {
+    fn read(&self, key: &str) -> Result<Option<String>, String> {
+        match std::fs::read_to_string(self.dir.join(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {key}: {e}")),
+        }
+    }
+
+    fn write(&self, key: &str, data: &str) -> Result<(), String> {
+        use std::io::Write;
+
+        // Renaming over the old file is atomic, so a crash leaves either the old or the new save
+        let path = self.dir.join(key);
+        let tmp_path = self.dir.join(format!("{key}.tmp"));
+        let replace = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&tmp_path)?;
+            file.write_all(data.as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&tmp_path, &path)?;
+            // The rename itself only survives a power loss once the directory is synced
+            #[cfg(unix)]
+            std::fs::File::open(&self.dir)?.sync_all()?;
+            Ok(())
+        };
+        replace().map_err(|e| {
+            let _ = std::fs::remove_file(&tmp_path);
+            format!("Failed to write {key}: {e}")
+        })
+    }
+}
+
+/// Browser `localStorage`, keys are prefixed with the game name.
+#[cfg(target_arch = "wasm32")]
+pub struct LocalStorageBackend;
+
+#[cfg(target_arch = "wasm32")]
+impl LocalStorageBackend {
+    fn storage() -> Result<web_sys::Storage, String> {
+        web_sys::window()
+            .and_then(|w| w.local_storage().ok().flatten())
+            .ok_or_else(|| "localStorage is not available".to_string())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl SaveBackend for LocalStorageBackend {
+    fn read(&self, key: &str) -> Result<Option<String>, String> {
+        Self::storage()?
+            .get_item(&format!("reggle/{key}"))
+            .map_err(|e| format!("Failed to read {key}: {e:?}"))
+    }
+
+    /// A single `setItem` call, which the browser applies atomically.
+    fn write(&self, key: &str, data: &str) -> Result<(), String> {
+        Self::storage()?
+            .set_item(&format!("reggle/{key}"), data)
+            .map_err(|e| format!("Failed to write {key}: {e:?}"))
+    }
+}
+
+/// Everything kept between runs.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct SaveData {
+    pub version: u32,
+    pub settings: Settings,
+    pub progress: CampaignProgress,
+}
+
+/// Why a save couldn't be read.
+enum ParseError {
+    /// Written by a newer build, which can still read it.
+    Newer(u32),
+    Invalid(String),
+}
+
+/// Enough of any save version to tell how to read the rest.
+#[derive(Deserialize)]
+struct SaveHeader {
+    #[serde(default)]
+    version: u32,
+}
+
+impl SaveData {
+    /// Reads a save of any known version, upgrading older ones to the current layout.
+    fn parse(data: &str) -> Result<Self, ParseError> {
+        let header: SaveHeader =
+            ron::from_str(data).map_err(|e| ParseError::Invalid(e.to_string()))?;
+        match header.version {
+            0 => ron::from_str(data)
+                .map(Self::migrate_v0)
+                .map_err(|e| ParseError::Invalid(e.to_string())),
+            SAVE_VERSION => ron::from_str(data).map_err(|e| ParseError::Invalid(e.to_string())),
+            version => Err(ParseError::Newer(version)),
+        }
+    }
+
+    /// Saves without a version field have the layout of version 1.
+    fn migrate_v0(mut save: SaveData) -> Self {
+        save.version = 1;
+        save
+    }
+
+    /// Builds the first save from the settings file of older builds.
+    fn migrate_legacy(backend: &dyn SaveBackend) -> Self {
+        let mut save = SaveData {
+            version: SAVE_VERSION,
+            ..Default::default()
+        };
+        match backend.read(LEGACY_SETTINGS_KEY) {
+            Ok(Some(data)) => match ron::from_str(&data) {
+                Ok(settings) => save.settings = settings,
+                Err(e) => warn!("Failed to migrate {LEGACY_SETTINGS_KEY}: {e}"),
+            },
+            Ok(None) => (),
+            Err(e) => warn!("{e}"),
+        }
+        save
+    }
+}
+
+#[derive(Resource)]
+pub struct SaveStore {
+    backend: Box<dyn SaveBackend>,
+    /// Unset when the existing save can't be read or backed up, or is newer than this build, so
+    /// it is not replaced.
+    writable: bool,
+}
+
+impl SaveStore {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn new() -> Self {
+        Self {
+            backend: Box::new(FileBackend {
+                dir: std::path::PathBuf::from("."),
+            }),
+            writable: true,
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn new() -> Self {
+        Self {
+            backend: Box::new(LocalStorageBackend),
+            writable: true,
+        }
+    }
+
+    fn load(&mut self) -> SaveData {
+        let data = match self.backend.read(SAVE_KEY) {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                let save = SaveData::migrate_legacy(&*self.backend);
+                self.write(&save);
+                return save;
+            }
+            Err(e) => {
+                error!("{e}");
+                self.writable = false;
+                return SaveData::default();
+            }
+        };
+        match SaveData::parse(&data) {
+            Ok(save) => save,
+            Err(ParseError::Newer(version)) => {
+                // Left as it is, for the build that wrote it
+                error!(
+                    "{SAVE_KEY} has version {version}, newer than this build supports \
+                    ({SAVE_VERSION}), progress won't be saved"
+                );
+                self.writable = false;
+                SaveData::default()
+            }
+            Err(ParseError::Invalid(e)) => {
+                error!("Failed to load {SAVE_KEY}: {e}");
+                // Starting over is fine as long as the old save is kept somewhere
+                match self.backend.write(BACKUP_KEY, &data) {
+                    Ok(()) => warn!("Moved the unreadable save to {BACKUP_KEY}"),
+                    Err(e) => {
+                        error!("{e}, progress won't be saved");
+                        self.writable = false;
+                    }
+                }
+                SaveData::default()
+            }
+        }
+    }
+
+    fn write(&self, save: &SaveData) {
+        if !self.writable {
+            return;
+        }
+        let data = match ron::ser::to_string_pretty(save, Default::default()) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to serialize save: {e}");
+                return;
+            }
+        };
+        if let Err(e) = self.backend.write(SAVE_KEY, &data) {
+            error!("{e}");
+        }
+    }
+}
+
+fn write_save(store: Res<SaveStore>, settings: Res<Settings>, progress: Res<CampaignProgress>) {
+    let settings_changed = settings.is_changed() && !settings.is_added();
+    let progress_changed = progress.is_changed() && !progress.is_added();
+    if !settings_changed && !progress_changed {
+        return;
+    }
+    store.write(&SaveData {
+        version: SAVE_VERSION,
+        settings: settings.clone(),
+        progress: progress.clone(),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::campaign::{LevelRecord, PackProgress};
+
+    /// Keys shared with the test, so it can look at what the store wrote.
+    #[derive(Clone, Default)]
+    struct MemoryBackend(Arc<Mutex<HashMap<String, String>>>);
+
+    impl MemoryBackend {
+        fn with(key: &str, data: &str) -> Self {
+            let backend = Self::default();
+            backend
+                .0
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), data.to_string());
+            backend
+        }
+
+        fn get(&self, key: &str) -> Option<String> {
+            self.0.lock().unwrap().get(key).cloned()
+        }
+    }
+
+    impl SaveBackend for MemoryBackend {
+        fn read(&self, key: &str) -> Result<Option<String>, String> {
+            Ok(self.get(key))
+        }
+
+        fn write(&self, key: &str, data: &str) -> Result<(), String> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), data.to_string());
+            Ok(())
+        }
+    }
+
+    /// Fails every read, like a save file without read permission.
+    struct BrokenBackend;
+
+    impl SaveBackend for BrokenBackend {
+        fn read(&self, key: &str) -> Result<Option<String>, String> {
+            Err(format!("Failed to read {key}"))
+        }
+
+        fn write(&self, _key: &str, _data: &str) -> Result<(), String> {
+            panic!("wrote to a save that couldn't be read");
+        }
+    }
+
+    fn store(backend: impl SaveBackend + 'static) -> SaveStore {
+        SaveStore {
+            backend: Box::new(backend),
+            writable: true,
+        }
+    }
+
+    fn sample_save() -> SaveData {
+        let mut pack = PackProgress::default();
+        pack.levels.insert(
+            "intro".to_string(),
+            LevelRecord {
+                unlocked: true,
+                cleared: true,
+                best_score: 4200,
+                fewest_balls: Some(3),
+            },
+        );
+        let mut save = SaveData {
+            version: SAVE_VERSION,
+            ..Default::default()
+        };
+        save.settings.theme = "midnight".to_string();
+        save.progress.packs.insert("classic".to_string(), pack);
+        save.progress.free_play_best = 1300;
+        save
+    }
+
+    #[test]
+    fn parses_current_version() {
+        let data = ron::ser::to_string_pretty(&sample_save(), Default::default()).unwrap();
+        let save = SaveData::parse(&data).unwrap();
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.settings.theme, "midnight");
+        assert_eq!(save.progress.free_play_best, 1300);
+        let record = save.progress.pack("classic").level("intro");
+        assert!(record.cleared);
+        assert_eq!(record.best_score, 4200);
+        assert_eq!(record.fewest_balls, Some(3));
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let newer = SaveData::parse(&format!("(version: {})", SAVE_VERSION + 1));
+        assert!(matches!(newer, Err(ParseError::Newer(v)) if v == SAVE_VERSION + 1));
+    }
+
+    #[test]
+    fn upgrades_save_without_version() {
+        let save = SaveData::parse(r#"(settings: (theme: "midnight"))"#).unwrap();
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.settings.theme, "midnight");
+    }
+
+    #[test]
+    fn migrates_legacy_settings() {
+        let backend = MemoryBackend::with(LEGACY_SETTINGS_KEY, r#"(theme: "midnight")"#);
+        let save = store(backend.clone()).load();
+        assert_eq!(save.settings.theme, "midnight");
+        let written = SaveData::parse(&backend.get(SAVE_KEY).unwrap()).unwrap();
+        assert_eq!(written.settings.theme, "midnight");
+    }
+
+    #[test]
+    fn backs_up_unreadable_save() {
+        let backend = MemoryBackend::with(SAVE_KEY, "not a save");
+        let mut store = store(backend.clone());
+        let save = store.load();
+        assert_eq!(save.settings.theme, Settings::default().theme);
+        assert!(store.writable);
+
+        store.write(&sample_save());
+        assert_eq!(backend.get(BACKUP_KEY).as_deref(), Some("not a save"));
+        let written = SaveData::parse(&backend.get(SAVE_KEY).unwrap()).unwrap();
+        assert_eq!(written.settings.theme, "midnight");
+    }
+
+    #[test]
+    fn keeps_newer_save_untouched() {
+        let data = format!("(version: {})", SAVE_VERSION + 1);
+        let backend = MemoryBackend::with(SAVE_KEY, &data);
+        let mut store = store(backend.clone());
+        store.load();
+        assert!(!store.writable);
+        assert_eq!(backend.get(BACKUP_KEY), None);
+
+        store.write(&sample_save());
+        assert_eq!(backend.get(SAVE_KEY), Some(data));
+    }
+
+    #[test]
+    fn keeps_save_it_could_not_read() {
+        let mut store = store(BrokenBackend);
+        store.load();
+        assert!(!store.writable);
+        store.write(&sample_save());
+    }
+}
@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Reflect, Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct ChannelVolume {
+    pub volume: f64,
+    pub muted: bool,
+}
+
+impl Default for ChannelVolume {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl ChannelVolume {
+    pub fn effective(&self) -> f64 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume.clamp(0.0, 1.0)
+        }
+    }
+}
+
+#[derive(Reflect, Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: ChannelVolume,
+    pub music: ChannelVolume,
+    pub peg_sfx: ChannelVolume,
+    pub ball_sfx: ChannelVolume,
+    pub ui: ChannelVolume,
+}
+
+/// Player settings, kept in the save.
+#[derive(Resource, Reflect, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    /// Key of the active theme in the asset manifest.
+    pub theme: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            audio: Default::default(),
+            theme: "default".to_string(),
+        }
+    }
+}
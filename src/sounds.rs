@@ -1,27 +1,136 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use bevy::asset::{AssetLoader, AssetPath, HandleId, LoadContext, LoadedAsset};
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use bevy_kira_audio::{Audio, AudioControl, AudioSource};
-use bevy_rapier2d::prelude::CollisionEvent;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::{BoxedFuture, HashMap};
+use bevy_kira_audio::prelude::*;
+use bevy_rapier2d::prelude::{CollisionEvent, RapierContext, Velocity};
+use serde::{Deserialize, Serialize};
 
+use crate::assets::GameAssets;
 use crate::common::GameState;
+use crate::input::{GameAction, GameInput};
+use crate::level::Level;
+use crate::settings::Settings;
+use crate::{ARENA_POS, ARENA_SIZE};
+
+/// Relative normal velocity at which collision sounds play at full volume.
+const COLLISION_FULL_VOLUME_SPEED: f32 = 600.0;
+/// Collisions weaker than this fraction of full volume are not heard at all.
+const COLLISION_MIN_STRENGTH: f32 = 0.03;
+/// How far from the center collision sounds can be panned. 0.5 is hard left/right.
+const COLLISION_PANNING_WIDTH: f32 = 0.4;
+/// Same sound can only start once within this many seconds.
+const SOUND_RATE_WINDOW_SECS: f64 = 0.05;
 
 pub struct SoundsPlugin;
 
 impl Plugin for SoundsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            play_collision_sound.run_if(in_state(GameState::InGame)),
-        );
+        app.add_audio_channel::<MusicChannel>()
+            .add_audio_channel::<PegSfxChannel>()
+            .add_audio_channel::<BallSfxChannel>()
+            .add_audio_channel::<UiChannel>()
+            .add_asset::<SoundBank>()
+            .init_asset_loader::<SoundBankLoader>()
+            .init_resource::<LevelMusic>()
+            .add_systems(
+                OnEnter(GameState::InGame),
+                (select_level_music, play_level_music).chain(),
+            )
+            .add_systems(OnExit(GameState::InGame), stop_level_music)
+            .add_systems(Update, (toggle_mute, apply_volume_settings).chain())
+            .add_systems(
+                Update,
+                play_collision_sound.run_if(in_state(GameState::InGame)),
+            );
     }
 }
+
+#[derive(Resource)]
+pub struct MusicChannel;
+
+#[derive(Resource)]
+pub struct PegSfxChannel;
+
+#[derive(Resource)]
+pub struct BallSfxChannel;
+
+#[derive(Resource)]
+pub struct UiChannel;
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum SoundChannel {
+    Music,
+    PegSfx,
+    BallSfx,
+    #[default]
+    Ui,
+}
+
+/// All game audio channels, so systems can play on a channel picked at runtime.
+#[derive(SystemParam)]
+pub struct AudioChannels<'w> {
+    music: Res<'w, AudioChannel<MusicChannel>>,
+    peg_sfx: Res<'w, AudioChannel<PegSfxChannel>>,
+    ball_sfx: Res<'w, AudioChannel<BallSfxChannel>>,
+    ui: Res<'w, AudioChannel<UiChannel>>,
+}
+
+impl AudioChannels<'_> {
+    pub fn play(&self, channel: SoundChannel, sound: Handle<AudioSource>) -> PlayAudioCommand<'_> {
+        match channel {
+            SoundChannel::Music => self.music.play(sound),
+            SoundChannel::PegSfx => self.peg_sfx.play(sound),
+            SoundChannel::BallSfx => self.ball_sfx.play(sound),
+            SoundChannel::Ui => self.ui.play(sound),
+        }
+    }
+}
+
+/// Track that loops in the background while the level is played.
+#[derive(Resource, Default)]
+pub struct LevelMusic {
+    pub track: Option<Handle<AudioSource>>,
+}
+
+/// Clips that are played one after another, wrapping around at the end.
+/// Progress is shared by everything that uses the same sequence.
+#[derive(Default)]
+pub struct SoundSequence {
+    clips: Vec<Handle<AudioSource>>,
+    next: AtomicUsize,
+}
+
+impl SoundSequence {
+    pub fn new(clips: Vec<Handle<AudioSource>>) -> Self {
+        Self {
+            clips,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn next_clip(&self) -> Option<&Handle<AudioSource>> {
+        if self.clips.is_empty() {
+            return None;
+        }
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % self.clips.len();
+        self.clips.get(idx)
+    }
+}
+
 #[derive(Default, Clone)]
 pub enum SoundType {
     #[default]
     None,
-    _Single(Handle<AudioSource>),
+    Single(Handle<AudioSource>),
     Random(Arc<Vec<Handle<AudioSource>>>),
+    Sequence(Arc<SoundSequence>),
+    /// All clips are played at once.
+    Layered(Arc<Vec<Handle<AudioSource>>>),
 }
 
 #[derive(Component, Clone)]
@@ -29,6 +138,9 @@ pub struct CollisionSound {
     pub sound: SoundType,
     pub volume: f64,
     pub priority: i32,
+    pub channel: SoundChannel,
+    /// Maximum deviation of the playback rate from 1.0, picked randomly on each play.
+    pub pitch_variation: f64,
 }
 
 impl Default for CollisionSound {
@@ -37,30 +149,232 @@ impl Default for CollisionSound {
             sound: SoundType::None,
             volume: 1.0,
             priority: 0,
+            channel: SoundChannel::default(),
+            pitch_variation: 0.0,
         }
     }
 }
 
 impl CollisionSound {
-    pub fn play(&self, audio: &Audio) {
+    /// Plays the sound with its volume scaled by `strength` and panned to `panning`,
+    /// where 0.0 is left, 0.5 is center and 1.0 is right.
+    pub fn play(&self, channels: &AudioChannels, strength: f64, panning: f64) {
         match &self.sound {
-            SoundType::_Single(h) => {
-                audio.play(h.clone());
-            }
+            SoundType::Single(h) => self.play_clip(channels, h, strength, panning),
             SoundType::Random(hs) => {
                 if let Some(h) = fastrand::choice(hs.as_ref()) {
-                    audio.play(h.clone()).with_volume(self.volume);
+                    self.play_clip(channels, h, strength, panning);
+                }
+            }
+            SoundType::Sequence(seq) => {
+                if let Some(h) = seq.next_clip() {
+                    self.play_clip(channels, h, strength, panning);
+                }
+            }
+            SoundType::Layered(hs) => {
+                for h in hs.iter() {
+                    self.play_clip(channels, h, strength, panning);
                 }
             }
             SoundType::None => (),
         };
     }
+
+    fn play_clip(
+        &self,
+        channels: &AudioChannels,
+        clip: &Handle<AudioSource>,
+        strength: f64,
+        panning: f64,
+    ) {
+        let playback_rate = 1.0 + (fastrand::f64() * 2.0 - 1.0) * self.pitch_variation;
+        channels
+            .play(self.channel, clip.clone())
+            .with_volume(self.volume * strength)
+            .with_panning(panning)
+            .with_playback_rate(playback_rate);
+    }
+}
+
+#[derive(Deserialize)]
+enum SoundTypeDef {
+    None,
+    Single(String),
+    Random(Vec<String>),
+    Sequence(Vec<String>),
+    Layered(Vec<String>),
+}
+
+fn default_volume() -> f64 {
+    1.0
+}
+
+/// [`CollisionSound`] as it is written in `*.sounds.ron` files, with clips given as asset paths.
+#[derive(Deserialize)]
+struct CollisionSoundDef {
+    sound: SoundTypeDef,
+    #[serde(default = "default_volume")]
+    volume: f64,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    channel: SoundChannel,
+    #[serde(default)]
+    pitch_variation: f64,
+}
+
+/// Named collision sounds loaded from a `*.sounds.ron` file.
+#[derive(TypeUuid, TypePath, Default)]
+#[uuid = "5b0f1b7e-3c2a-4d5e-9a57-2f0c8f6d1e43"]
+pub struct SoundBank(pub HashMap<String, CollisionSound>);
+
+impl SoundType {
+    pub fn clips(&self) -> &[Handle<AudioSource>] {
+        match self {
+            SoundType::None => &[],
+            SoundType::Single(h) => std::slice::from_ref(h),
+            SoundType::Random(hs) | SoundType::Layered(hs) => hs.as_slice(),
+            SoundType::Sequence(seq) => &seq.clips,
+        }
+    }
+}
+
+impl SoundBank {
+    pub fn get(&self, name: &str) -> CollisionSound {
+        self.0.get(name).cloned().unwrap_or_else(|| {
+            warn!("Sound \"{name}\" is missing from the sound bank");
+            CollisionSound::default()
+        })
+    }
+
+    pub fn clips(&self) -> impl Iterator<Item = &Handle<AudioSource>> {
+        self.0.values().flat_map(|s| s.sound.clips())
+    }
+}
+
+#[derive(Default)]
+pub struct SoundBankLoader;
+
+impl AssetLoader for SoundBankLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let defs: HashMap<String, CollisionSoundDef> = ron::de::from_bytes(bytes)?;
+            let mut dependencies = Vec::new();
+            let mut clip = |path: String| -> Handle<AudioSource> {
+                let asset_path = AssetPath::from(path.as_str()).to_owned();
+                dependencies.push(asset_path.clone());
+                load_context.get_handle(asset_path)
+            };
+            let mut sounds = HashMap::new();
+            for (name, def) in defs {
+                let sound = match def.sound {
+                    SoundTypeDef::None => SoundType::None,
+                    SoundTypeDef::Single(path) => SoundType::Single(clip(path)),
+                    SoundTypeDef::Random(paths) => {
+                        SoundType::Random(Arc::new(paths.into_iter().map(&mut clip).collect()))
+                    }
+                    SoundTypeDef::Sequence(paths) => SoundType::Sequence(Arc::new(
+                        SoundSequence::new(paths.into_iter().map(&mut clip).collect()),
+                    )),
+                    SoundTypeDef::Layered(paths) => {
+                        SoundType::Layered(Arc::new(paths.into_iter().map(&mut clip).collect()))
+                    }
+                };
+                let collision_sound = CollisionSound {
+                    sound,
+                    volume: def.volume,
+                    priority: def.priority,
+                    channel: def.channel,
+                    pitch_variation: def.pitch_variation,
+                };
+                sounds.insert(name, collision_sound);
+            }
+            load_context.set_default_asset(
+                LoadedAsset::new(SoundBank(sounds)).with_dependencies(dependencies),
+            );
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["sounds.ron"]
+    }
+}
+
+/// Lets every sound start only once within a short window, so many pegs hit at once
+/// don't stack the same sound into noise. Different sounds are never held back.
+#[derive(Default)]
+pub struct SoundRateLimiter {
+    /// When sounds last started, by channel and the first clip of the sound.
+    last_played: HashMap<(SoundChannel, HandleId), f64>,
+}
+
+impl SoundRateLimiter {
+    pub fn try_play(&mut self, sound: &CollisionSound, now: f64) -> bool {
+        let Some(clip) = sound.sound.clips().first() else {return true};
+        self.last_played
+            .retain(|_, started| now - *started <= SOUND_RATE_WINDOW_SECS);
+        let key = (sound.channel, clip.id());
+        if self.last_played.contains_key(&key) {
+            return false;
+        }
+        self.last_played.insert(key, now);
+        true
+    }
+}
+
+fn arena_panning(x: f32) -> f64 {
+    let rel_x = ((x - ARENA_POS.x) / ARENA_SIZE.x).clamp(-0.5, 0.5);
+    (0.5 + rel_x * COLLISION_PANNING_WIDTH * 2.0) as f64
+}
+
+/// Returns world position of the contact and how hard it was, from 0.0 to 1.0.
+fn contact_pos_and_strength(
+    e1: Entity,
+    e2: Entity,
+    rapier_ctx: &RapierContext,
+    bodies: &Query<(&GlobalTransform, Option<&Velocity>)>,
+) -> (Vec2, f32) {
+    let linvel = |e| {
+        bodies
+            .get(e)
+            .ok()
+            .and_then(|(_, vel)| vel)
+            .map_or(Vec2::ZERO, |vel| vel.linvel)
+    };
+    let rel_vel = linvel(e1) - linvel(e2);
+    let fallback_pos = bodies
+        .get(e1)
+        .map_or(Vec2::ZERO, |(tr, _)| tr.translation().truncate());
+
+    let manifold = rapier_ctx
+        .contact_pair(e1, e2)
+        .and_then(|pair| pair.manifolds().next());
+    let Some(manifold) = manifold else {
+        return (
+            fallback_pos,
+            (rel_vel.length() / COLLISION_FULL_VOLUME_SPEED).min(1.0),
+        );
+    };
+    let pos = manifold
+        .solver_contact(0)
+        .map_or(fallback_pos, |c| c.point() * rapier_ctx.physics_scale());
+    let speed = rel_vel.dot(manifold.normal()).abs();
+    (pos, (speed / COLLISION_FULL_VOLUME_SPEED).min(1.0))
 }
 
 pub fn play_collision_sound(
     mut collision_events: EventReader<CollisionEvent>,
     ents: Query<&CollisionSound>,
-    audio: Res<Audio>,
+    bodies: Query<(&GlobalTransform, Option<&Velocity>)>,
+    rapier_ctx: Res<RapierContext>,
+    time: Res<Time>,
+    mut rate_limiter: Local<SoundRateLimiter>,
+    channels: AudioChannels,
 ) {
     for e in collision_events.iter() {
         let CollisionEvent::Started(e1, e2, _) = e else {continue};
@@ -79,8 +393,67 @@ pub fn play_collision_sound(
             (Err(_), Ok(cs)) => [Some(cs), None],
             (Err(_), Err(_)) => [None, None],
         };
+        if css.iter().all(Option::is_none) {
+            continue;
+        }
+        let (pos, strength) = contact_pos_and_strength(*e1, *e2, &rapier_ctx, &bodies);
+        if strength < COLLISION_MIN_STRENGTH {
+            continue;
+        }
+        let panning = arena_panning(pos.x);
         for cs in css.iter().filter_map(|x| *x) {
-            cs.play(&audio)
+            if rate_limiter.try_play(cs, time.elapsed_seconds_f64()) {
+                cs.play(&channels, strength as f64, panning)
+            }
         }
     }
 }
+
+/// Picks the track of the level that is about to be played.
+fn select_level_music(
+    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
+    levels: Res<Assets<Level>>,
+    mut level_music: ResMut<LevelMusic>,
+) {
+    let music = levels
+        .get(&game_assets.level)
+        .and_then(|level| level.music.as_deref());
+    level_music.track = music.map(|path| asset_server.load(path));
+}
+
+fn play_level_music(level_music: Res<LevelMusic>, music: Res<AudioChannel<MusicChannel>>) {
+    music.stop();
+    if let Some(track) = &level_music.track {
+        music.play(track.clone()).looped();
+    }
+}
+
+fn stop_level_music(music: Res<AudioChannel<MusicChannel>>) {
+    music.stop();
+}
+
+fn toggle_mute(input_state: Res<GameInput>, mut settings: ResMut<Settings>) {
+    if input_state.just_active(GameAction::ToggleMute) {
+        let master = &mut settings.audio.master;
+        master.muted = !master.muted;
+    }
+}
+
+fn apply_volume_settings(
+    settings: Res<Settings>,
+    music: Res<AudioChannel<MusicChannel>>,
+    peg_sfx: Res<AudioChannel<PegSfxChannel>>,
+    ball_sfx: Res<AudioChannel<BallSfxChannel>>,
+    ui: Res<AudioChannel<UiChannel>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let audio = &settings.audio;
+    let master = audio.master.effective();
+    music.set_volume(master * audio.music.effective());
+    peg_sfx.set_volume(master * audio.peg_sfx.effective());
+    ball_sfx.set_volume(master * audio.ball_sfx.effective());
+    ui.set_volume(master * audio.ui.effective());
+}
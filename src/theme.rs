@@ -0,0 +1,250 @@
+use bevy::asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::Deserialize;
+
+use crate::assets::GameAssets;
+use crate::ball::Ball;
+use crate::common::{Background, GameState, Wall};
+use crate::input::{GameAction, GameInput};
+use crate::launcher::Launcher;
+use crate::settings::Settings;
+use crate::sounds::SoundBank;
+
+pub struct ThemePlugin;
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Theme>()
+            .init_asset_loader::<ThemeLoader>()
+            .init_resource::<Themes>()
+            .add_systems(OnExit(GameState::LoadingAssets), select_theme)
+            .add_systems(
+                Update,
+                (
+                    switch_theme,
+                    refresh_themed_entities.run_if(resource_changed::<GameAssets>()),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::InGame)),
+            );
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct PegColors {
+    pub active: Color,
+    pub hit: Color,
+}
+
+/// Look of the game: textures, colors, sounds and font.
+#[derive(TypeUuid, TypePath)]
+#[uuid = "8f4e2a61-0b7c-4d3e-a5f9-6c1d2e3b4a59"]
+pub struct Theme {
+    pub name: String,
+    pub peg_image: Handle<Image>,
+    pub peg_hit_image: Handle<Image>,
+    pub ball_image: Handle<Image>,
+    pub launcher_image: Handle<Image>,
+    pub background_image: Option<Handle<Image>>,
+    pub font: Handle<Font>,
+    pub sound_bank: Handle<SoundBank>,
+    /// Peg colors keyed by peg kind.
+    pub peg_colors: HashMap<String, PegColors>,
+    pub wall_color: Color,
+}
+
+impl Theme {
+    pub fn images(&self) -> impl Iterator<Item = &Handle<Image>> {
+        [
+            &self.peg_image,
+            &self.peg_hit_image,
+            &self.ball_image,
+            &self.launcher_image,
+        ]
+        .into_iter()
+        .chain(self.background_image.as_ref())
+    }
+}
+
+#[derive(Deserialize)]
+struct PegColorsDef {
+    active: String,
+    hit: String,
+}
+
+/// [`Theme`] as it is written in `*.theme.ron` files.
+#[derive(Deserialize)]
+struct ThemeDef {
+    name: String,
+    peg_image: String,
+    peg_hit_image: String,
+    ball_image: String,
+    launcher_image: String,
+    #[serde(default)]
+    background_image: Option<String>,
+    font: String,
+    sounds: String,
+    peg_colors: HashMap<String, PegColorsDef>,
+    wall_color: String,
+}
+
+#[derive(Default)]
+pub struct ThemeLoader;
+
+impl AssetLoader for ThemeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let def: ThemeDef = ron::de::from_bytes(bytes)?;
+            let mut peg_colors = HashMap::new();
+            for (kind, colors) in def.peg_colors {
+                let colors = PegColors {
+                    active: Color::hex(colors.active)?,
+                    hit: Color::hex(colors.hit)?,
+                };
+                peg_colors.insert(kind, colors);
+            }
+            let wall_color = Color::hex(def.wall_color)?;
+
+            let mut dependencies = Vec::new();
+            let mut dependency = |path: &str| {
+                let asset_path = AssetPath::from(path).to_owned();
+                dependencies.push(asset_path.clone());
+                asset_path
+            };
+            let theme = Theme {
+                name: def.name,
+                peg_image: load_context.get_handle(dependency(&def.peg_image)),
+                peg_hit_image: load_context.get_handle(dependency(&def.peg_hit_image)),
+                ball_image: load_context.get_handle(dependency(&def.ball_image)),
+                launcher_image: load_context.get_handle(dependency(&def.launcher_image)),
+                background_image: def
+                    .background_image
+                    .map(|path| load_context.get_handle(dependency(&path))),
+                font: load_context.get_handle(dependency(&def.font)),
+                sound_bank: load_context.get_handle(dependency(&def.sounds)),
+                peg_colors,
+                wall_color,
+            };
+            load_context.set_default_asset(LoadedAsset::new(theme).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["theme.ron"]
+    }
+}
+
+/// Every theme from the asset manifest, sorted by key.
+#[derive(Resource, Default)]
+pub struct Themes {
+    pub themes: Vec<(String, Handle<Theme>)>,
+    pub active: usize,
+}
+
+impl Themes {
+    pub fn active(&self) -> Option<&Handle<Theme>> {
+        self.themes.get(self.active).map(|(_, h)| h)
+    }
+}
+
+fn apply_theme(theme: &Theme, sound_banks: &Assets<SoundBank>, assets: &mut GameAssets) {
+    assets.peg.image = theme.peg_image.clone();
+    assets.peg.hit_image = theme.peg_hit_image.clone();
+    assets.peg.colors = theme.peg_colors.clone();
+    assets.ball.image = theme.ball_image.clone();
+    assets.launcher.image = theme.launcher_image.clone();
+    assets.background_image = theme.background_image.clone();
+    assets.normal_font = theme.font.clone();
+    assets.wall_color = theme.wall_color;
+    assets.sound_bank = theme.sound_bank.clone();
+
+    let Some(bank) = sound_banks.get(&theme.sound_bank) else {return};
+    assets.peg.pop_sound = bank.get("peg_pop");
+    assets.peg.explode_sound = bank.get("peg_explode");
+    assets.ball.hit_sound = bank.get("ball_hit");
+}
+
+fn select_theme(
+    mut themes: ResMut<Themes>,
+    settings: Res<Settings>,
+    theme_assets: Res<Assets<Theme>>,
+    sound_banks: Res<Assets<SoundBank>>,
+    mut game_assets: ResMut<GameAssets>,
+) {
+    themes.active = themes
+        .themes
+        .iter()
+        .position(|(key, _)| *key == settings.theme)
+        .unwrap_or_default();
+    let Some(theme) = themes.active().and_then(|h| theme_assets.get(h)) else {return};
+    apply_theme(theme, &sound_banks, &mut game_assets);
+}
+
+fn switch_theme(
+    input_state: Res<GameInput>,
+    mut themes: ResMut<Themes>,
+    mut settings: ResMut<Settings>,
+    theme_assets: Res<Assets<Theme>>,
+    sound_banks: Res<Assets<SoundBank>>,
+    mut game_assets: ResMut<GameAssets>,
+) {
+    if !input_state.just_active(GameAction::NextTheme) || themes.themes.is_empty() {
+        return;
+    }
+    themes.active = (themes.active + 1) % themes.themes.len();
+    let (key, handle) = &themes.themes[themes.active];
+    let Some(theme) = theme_assets.get(handle) else {return};
+    info!("Switching to theme \"{}\"", theme.name);
+    settings.theme = key.clone();
+    apply_theme(theme, &sound_banks, &mut game_assets);
+}
+
+fn refresh_themed_entities(
+    mut commands: Commands,
+    game_assets: Res<GameAssets>,
+    mut background: Query<(Entity, &mut Handle<Image>), With<Background>>,
+    mut walls: Query<&mut Sprite, With<Wall>>,
+    mut textures: Query<
+        (&mut Handle<Image>, Option<&Ball>),
+        (Or<(With<Ball>, With<Launcher>)>, Without<Background>),
+    >,
+    mut texts: Query<&mut Text>,
+) {
+    match (background.get_single_mut(), &game_assets.background_image) {
+        (Ok((_, mut texture)), Some(image)) => *texture = image.clone(),
+        (Ok((entity, _)), None) => commands.entity(entity).despawn(),
+        (Err(_), Some(image)) => {
+            commands.spawn((
+                SpriteBundle {
+                    texture: image.clone(),
+                    transform: Transform::from_xyz(0.0, 0.0, -100.0),
+                    ..Default::default()
+                },
+                Background,
+            ));
+        }
+        (Err(_), None) => (),
+    }
+    for mut sprite in walls.iter_mut() {
+        sprite.color = game_assets.wall_color;
+    }
+    for (mut texture, ball) in textures.iter_mut() {
+        *texture = if ball.is_some() {
+            game_assets.ball.image.clone()
+        } else {
+            game_assets.launcher.image.clone()
+        };
+    }
+    for mut text in texts.iter_mut() {
+        for section in text.sections.iter_mut() {
+            section.style.font = game_assets.normal_font.clone();
+        }
+    }
+}
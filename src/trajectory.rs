@@ -1,9 +1,10 @@
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
 use bevy::utils::{HashMap, HashSet};
 use bevy_prototype_lyon::prelude::{Fill, GeometryBuilder, PathBuilder, ShapeBundle, Stroke};
 use bevy_prototype_lyon::shapes::Circle;
 use bevy_rapier2d::na;
-use bevy_rapier2d::prelude::{Collider, RapierConfiguration, Restitution};
+use bevy_rapier2d::prelude::{Collider, RapierConfiguration, Restitution, Sensor};
 use bevy_rapier2d::rapier::prelude::{
     BroadPhase, CCDSolver, ColliderBuilder, ColliderHandle, ColliderSet, ImpulseJointSet,
     IntegrationParameters, IslandManager, MultibodyJointSet, NarrowPhase, PhysicsPipeline,
@@ -12,7 +13,9 @@ use bevy_rapier2d::rapier::prelude::{
 
 use crate::ball::BallPhysicsBundle;
 use crate::common::{GameState, InGameState};
+use crate::elements::{Bumper, GravityWell, LevelElement, Portal};
 use crate::launcher::Launcher;
+use crate::GRAVITY;
 use crate::PIXELS_PER_METER;
 use crate::PLAYER_BALL_RADIUS;
 
@@ -32,18 +35,29 @@ impl Plugin for TrajectoryPlugin {
                 OnExit(InGameState::Launcher),
                 despawn_trajectory_line.run_if(in_state(GameState::InGame)),
             )
+            .add_systems(OnExit(GameState::InGame), despawn_trajectory_line)
             .add_systems(
                 PostUpdate,
-                sync_colliders_system.run_if(in_state(GameState::InGame)),
+                (sync_colliders_system, sync_level_elements_system)
+                    .after(TransformSystem::TransformPropagate)
+                    .run_if(in_state(GameState::InGame)),
             );
     }
 }
 
+/// Level element behaviour the simulation has to mirror besides plain colliders.
+enum TrajectoryEffect {
+    Bumper(Bumper),
+    Portal(Portal),
+    GravityWell(Vec2, GravityWell),
+}
+
 #[derive(Resource)]
 pub struct TrajectoryWorld {
     scale: f32,
     scaled_shape_subdivision: u32,
     colliders: HashMap<Entity, ColliderHandle>,
+    effects: HashMap<Entity, TrajectoryEffect>,
     collider_set: ColliderSet,
     rigid_body_set: RigidBodySet,
     impulse_joint_set: ImpulseJointSet,
@@ -57,6 +71,10 @@ pub struct TrajectoryWorld {
 
     trajectory_points: Vec<Vec2>,
     collision_points: Vec<Vec2>,
+    /// Entities behind `collision_points`, in the same order.
+    collision_entities: Vec<Entity>,
+    /// Indices of trajectory points the body was teleported to.
+    trajectory_breaks: Vec<usize>,
 }
 
 impl TrajectoryWorld {
@@ -65,6 +83,7 @@ impl TrajectoryWorld {
             scale,
             scaled_shape_subdivision,
             colliders: HashMap::new(),
+            effects: HashMap::new(),
             collider_set: ColliderSet::new(),
             rigid_body_set: RigidBodySet::new(),
             physics_pipeline: PhysicsPipeline::new(),
@@ -78,9 +97,26 @@ impl TrajectoryWorld {
 
             trajectory_points: Vec::new(),
             collision_points: Vec::new(),
+            collision_entities: Vec::new(),
+            trajectory_breaks: Vec::new(),
         }
     }
 
+    /// World that isn't synced with any entities, for checking levels outside of the game.
+    pub fn headless() -> Self {
+        Self::new(
+            PIXELS_PER_METER,
+            RapierConfiguration::default().scaled_shape_subdivision,
+            GRAVITY / PIXELS_PER_METER,
+        )
+    }
+
+    /// Forgets every mirrored collider and effect.
+    pub fn clear(&mut self) {
+        let gravity = Vec2::new(self.gravity.x, self.gravity.y);
+        *self = Self::new(self.scale, self.scaled_shape_subdivision, gravity);
+    }
+
     fn remove_collider(&mut self, entity: Entity) {
         if let Some(collider_handle) = self.colliders.get(&entity) {
             self.collider_set.remove(
@@ -93,14 +129,28 @@ impl TrajectoryWorld {
         self.colliders.remove(&entity);
     }
 
-    fn add_collider(&mut self, entity: Entity, collider: &Collider, translation: Vec2) {
+    pub fn add_collider(
+        &mut self,
+        entity: Entity,
+        collider: &Collider,
+        transform: &GlobalTransform,
+        restitution: Option<&Restitution>,
+        sensor: bool,
+    ) {
         let scaled_shape = collider
             .as_unscaled_typed_shape()
             .raw_scale_by(Vec2::ONE / self.scale, self.scaled_shape_subdivision)
             .unwrap();
-        let rapier_collider = ColliderBuilder::new(scaled_shape)
-            .translation((translation / self.scale).into())
-            .build();
+        let mut builder = ColliderBuilder::new(scaled_shape)
+            .position(self.isometry(transform))
+            .sensor(sensor)
+            .user_data(entity.to_bits() as u128);
+        if let Some(restitution) = restitution {
+            builder = builder
+                .restitution(restitution.coefficient)
+                .restitution_combine_rule(restitution.combine_rule.into());
+        }
+        let rapier_collider = builder.build();
         let handle = self.collider_set.insert(rapier_collider);
         self.colliders.insert(entity, handle);
     }
@@ -117,14 +167,22 @@ impl TrajectoryWorld {
         };
     }
 
-    fn move_collider(&mut self, entity: Entity, translation: Vec2) {
+    fn move_collider(&mut self, entity: Entity, transform: &GlobalTransform) {
+        let position = self.isometry(transform);
         if let Some(handle) = self.colliders.get(&entity) {
             if let Some(rapier_collider) = self.collider_set.get_mut(*handle) {
-                rapier_collider.set_translation((translation / self.scale).into());
+                rapier_collider.set_position(position);
             }
         };
     }
 
+    fn isometry(&self, transform: &GlobalTransform) -> na::Isometry2<f32> {
+        let (_, rotation, translation) = transform.to_scale_rotation_translation();
+        let translation = translation.truncate() / self.scale;
+        let (angle, _, _) = rotation.to_euler(EulerRot::ZYX);
+        na::Isometry2::new(translation.into(), angle)
+    }
+
     fn simulate_body_trajectory(
         &mut self,
         start_pos: Vec2,
@@ -133,7 +191,7 @@ impl TrajectoryWorld {
         restitution: &Restitution,
         mut max_collisions: usize,
         max_trajectory_points: usize,
-    ) -> (&[Vec2], &[Vec2]) {
+    ) -> (&[Vec2], &[Vec2], &[usize]) {
         let rigid_body = RigidBodyBuilder::dynamic()
             .translation((start_pos / self.scale).into())
             .linvel((linvel / self.scale).into())
@@ -158,8 +216,25 @@ impl TrajectoryWorld {
 
         self.trajectory_points.clear();
         self.collision_points.clear();
+        self.collision_entities.clear();
+        self.trajectory_breaks.clear();
         let mut encountered_colliders = HashSet::new();
+        let mut portal_cooldown = None;
         for _ in 0..max_trajectory_points {
+            let body = &mut self.rigid_body_set[body_handle];
+            let position: Vec2 = (*body.translation() * self.scale).into();
+            let mut accel = Vec2::ZERO;
+            for effect in self.effects.values() {
+                if let TrajectoryEffect::GravityWell(well_pos, well) = effect {
+                    accel += well.acceleration(*well_pos, position);
+                }
+            }
+            if accel != Vec2::ZERO {
+                let linvel = *body.linvel()
+                    + na::Vector2::from(accel / self.scale) * integration_parameters.dt;
+                body.set_linvel(linvel, true);
+            }
+
             self.physics_pipeline.step(
                 &self.gravity,
                 &integration_parameters,
@@ -177,7 +252,37 @@ impl TrajectoryWorld {
             );
 
             let body = &self.rigid_body_set[body_handle];
-            let position = (*body.translation() * self.scale).into();
+            let position: Vec2 = (*body.translation() * self.scale).into();
+
+            // Portals are sensors, so they don't show up in contacts
+            let mut teleport = None;
+            let mut inside_cooldown_portal = false;
+            for (c1, c2, intersecting) in self.narrow_phase.intersections_with(body_collider_handle)
+            {
+                let other = if c1 != body_collider_handle { c1 } else { c2 };
+                if !intersecting {
+                    continue;
+                }
+                let other_entity = Entity::from_bits(self.collider_set[other].user_data as u64);
+                if portal_cooldown == Some(other_entity) {
+                    inside_cooldown_portal = true;
+                } else if let Some(TrajectoryEffect::Portal(portal)) =
+                    self.effects.get(&other_entity)
+                {
+                    teleport = Some(*portal);
+                }
+            }
+            if !inside_cooldown_portal {
+                portal_cooldown = None;
+            }
+            if let Some(portal) = teleport {
+                let body = &mut self.rigid_body_set[body_handle];
+                body.set_translation((portal.exit_pos / self.scale).into(), true);
+                portal_cooldown = Some(portal.exit);
+                self.trajectory_breaks.push(self.trajectory_points.len());
+                self.trajectory_points.push(portal.exit_pos);
+                continue;
+            }
 
             self.trajectory_points.push(position);
             if let Some(pair) = self.narrow_phase.contacts_with(body_collider_handle).next() {
@@ -193,6 +298,16 @@ impl TrajectoryWorld {
                 }
                 encountered_colliders.insert(other_collider);
                 self.collision_points.push(position);
+                let other_entity =
+                    Entity::from_bits(self.collider_set[other_collider].user_data as u64);
+                self.collision_entities.push(other_entity);
+                if let Some(TrajectoryEffect::Bumper(bumper)) = self.effects.get(&other_entity) {
+                    let bumper_pos =
+                        (*self.collider_set[other_collider].translation() * self.scale).into();
+                    let boost = bumper.boost(bumper_pos, position) / self.scale;
+                    let body = &mut self.rigid_body_set[body_handle];
+                    body.set_linvel(*body.linvel() + na::Vector2::from(boost), true);
+                }
                 max_collisions -= 1;
                 if max_collisions == 0 {
                     break;
@@ -208,7 +323,30 @@ impl TrajectoryWorld {
             true,
         );
 
-        (&self.trajectory_points, &self.collision_points)
+        (
+            &self.trajectory_points,
+            &self.collision_points,
+            &self.trajectory_breaks,
+        )
+    }
+
+    /// Entities a ball shot from `start_pos` collides with, in order.
+    pub fn shot_collisions(
+        &mut self,
+        start_pos: Vec2,
+        linvel: Vec2,
+        max_collisions: usize,
+    ) -> &[Entity] {
+        let ball = BallPhysicsBundle::new(start_pos.extend(0.0));
+        self.simulate_body_trajectory(
+            start_pos,
+            linvel,
+            &ball.collider,
+            &ball.restitution,
+            max_collisions,
+            600,
+        );
+        &self.collision_entities
     }
 }
 
@@ -222,25 +360,57 @@ pub fn init_trajectory_world(mut commands: Commands, rapier_config: Res<RapierCo
 
 pub fn sync_colliders_system(
     mut trajectory_world: ResMut<TrajectoryWorld>,
-    added_colliders: Query<(Entity, &Transform, &Collider), Added<Collider>>,
+    added_colliders: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            &Collider,
+            Option<&Restitution>,
+            Option<&Sensor>,
+        ),
+        Added<Collider>,
+    >,
     changed_colliders: Query<(Entity, &Collider), Changed<Collider>>,
-    moved_colliders: Query<(Entity, &Transform), (Changed<Transform>, With<Collider>)>,
+    moved_colliders: Query<(Entity, &GlobalTransform), (Changed<GlobalTransform>, With<Collider>)>,
     mut removed_colliders: RemovedComponents<Collider>,
 ) {
     changed_colliders.for_each(|(entity, collider)| {
         trajectory_world.update_collider(entity, collider);
     });
     moved_colliders.for_each(|(entity, transform)| {
-        trajectory_world.move_collider(entity, transform.translation.truncate());
+        trajectory_world.move_collider(entity, transform);
     });
-    added_colliders.for_each(|(entity, transform, collider)| {
-        trajectory_world.add_collider(entity, collider, transform.translation.truncate());
+    added_colliders.for_each(|(entity, transform, collider, restitution, sensor)| {
+        trajectory_world.add_collider(entity, collider, transform, restitution, sensor.is_some());
     });
     for entity in removed_colliders.iter() {
         trajectory_world.remove_collider(entity);
     }
 }
 
+pub fn sync_level_elements_system(
+    mut trajectory_world: ResMut<TrajectoryWorld>,
+    bumpers: Query<(Entity, &Bumper), Added<Bumper>>,
+    portals: Query<(Entity, &Portal), Added<Portal>>,
+    wells: Query<(Entity, &Transform, &GravityWell), Added<GravityWell>>,
+    mut removed_elements: RemovedComponents<LevelElement>,
+) {
+    let effects = &mut trajectory_world.effects;
+    for (entity, bumper) in bumpers.iter() {
+        effects.insert(entity, TrajectoryEffect::Bumper(*bumper));
+    }
+    for (entity, portal) in portals.iter() {
+        effects.insert(entity, TrajectoryEffect::Portal(*portal));
+    }
+    for (entity, transform, well) in wells.iter() {
+        let position = transform.translation.truncate();
+        effects.insert(entity, TrajectoryEffect::GravityWell(position, *well));
+    }
+    for entity in removed_elements.iter() {
+        effects.remove(&entity);
+    }
+}
+
 #[derive(Component)]
 pub struct TrajectoryLine;
 
@@ -258,19 +428,24 @@ fn draw_trajectory_system(
     if let Ok((launcher_tr, launcher)) = launcher.get_single() {
         let ball_bundle = BallPhysicsBundle::new(launcher_tr.translation);
         let start_pos = launcher_tr.translation.truncate();
-        let (trajectory_points, collision_points) = trajectory_world.simulate_body_trajectory(
-            start_pos,
-            launcher.get_impulse(),
-            &ball_bundle.collider,
-            &ball_bundle.restitution,
-            1,
-            200,
-        );
+        let (trajectory_points, collision_points, breaks) = trajectory_world
+            .simulate_body_trajectory(
+                start_pos,
+                launcher.get_impulse(),
+                &ball_bundle.collider,
+                &ball_bundle.restitution,
+                1,
+                200,
+            );
 
         let mut path_builder = PathBuilder::new();
         path_builder.move_to(start_pos);
-        for point in trajectory_points {
-            path_builder.line_to(*point);
+        for (i, point) in trajectory_points.iter().enumerate() {
+            if breaks.contains(&i) {
+                path_builder.move_to(*point);
+            } else {
+                path_builder.line_to(*point);
+            }
         }
         let line = path_builder.build();
 
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::assets::GameAssets;
+use crate::assets::{GameAssets, LoadingProgress};
 use crate::common::GameState;
 use crate::GameStats;
 
@@ -8,7 +8,15 @@ pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::InGame), setup_ui)
+        app.add_systems(OnEnter(GameState::LoadingAssets), setup_loading_ui)
+            .add_systems(
+                Update,
+                update_loading_progress_system.run_if(in_state(GameState::LoadingAssets)),
+            )
+            .add_systems(OnExit(GameState::LoadingAssets), despawn_loading_ui)
+            .add_systems(OnEnter(GameState::LoadingFailed), setup_loading_failed_ui)
+            .add_systems(OnEnter(GameState::InGame), setup_ui)
+            .add_systems(OnExit(GameState::InGame), despawn_ui)
             .add_systems(
                 Update,
                 update_score_system.run_if(in_state(GameState::InGame)),
@@ -19,6 +27,101 @@ impl Plugin for UiPlugin {
 #[derive(Component)]
 struct ScoreUi;
 
+#[derive(Component)]
+struct LoadingUi;
+
+#[derive(Component)]
+struct LoadingProgressBar;
+
+fn setup_loading_ui(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            LoadingUi,
+        ))
+        .with_children(|cb| {
+            cb.spawn(NodeBundle {
+                style: Style {
+                    width: Val::Percent(50.0),
+                    height: Val::Px(20.0),
+                    ..default()
+                },
+                background_color: Color::DARK_GRAY.into(),
+                ..default()
+            })
+            .with_children(|cb| {
+                cb.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        background_color: Color::WHITE.into(),
+                        ..default()
+                    },
+                    LoadingProgressBar,
+                ));
+            });
+        });
+}
+
+fn update_loading_progress_system(
+    asset_server: Res<AssetServer>,
+    progress: Res<LoadingProgress>,
+    mut bars: Query<&mut Style, With<LoadingProgressBar>>,
+) {
+    let progress = progress.progress(&asset_server);
+    for mut style in bars.iter_mut() {
+        style.width = Val::Percent(progress * 100.0);
+    }
+}
+
+fn despawn_loading_ui(mut commands: Commands, loading_ui: Query<Entity, With<LoadingUi>>) {
+    for e in loading_ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
+fn setup_loading_failed_ui(mut commands: Commands, progress: Res<LoadingProgress>) {
+    let style = TextStyle {
+        font_size: 24.0,
+        color: Color::WHITE,
+        ..Default::default()
+    };
+    let mut sections = vec![TextSection::new(
+        "Failed to load assets:\n",
+        TextStyle {
+            font_size: 32.0,
+            color: Color::ORANGE_RED,
+            ..Default::default()
+        },
+    )];
+    sections.extend(
+        progress
+            .errors
+            .iter()
+            .map(|e| TextSection::new(format!("{e}\n"), style.clone())),
+    );
+    commands.spawn(TextBundle {
+        text: Text::from_sections(sections),
+        style: Style {
+            margin: UiRect::all(Val::Px(20.0)),
+            ..default()
+        },
+        ..Default::default()
+    });
+}
+
 fn setup_ui(mut commands: Commands, game_assets: Res<GameAssets>) {
     commands
         .spawn(TextBundle {
@@ -42,8 +145,17 @@ fn setup_ui(mut commands: Commands, game_assets: Res<GameAssets>) {
         .insert(ScoreUi);
 }
 
+fn despawn_ui(mut commands: Commands, score_ui: Query<Entity, With<ScoreUi>>) {
+    for e in score_ui.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
+
 fn update_score_system(game_state: Res<GameStats>, mut score_ui: Query<&mut Text, With<ScoreUi>>) {
     if let Ok(mut text) = score_ui.get_single_mut() {
-        text.sections[0].value = game_state.player_score.to_string()
+        text.sections[0].value = match game_state.balls_left {
+            Some(balls) => format!("{}  Balls: {balls}", game_state.player_score),
+            None => game_state.player_score.to_string(),
+        };
     }
 }